@@ -114,5 +114,9 @@ fn log_problems<E>(entry: SentenceResult<E>) -> Option<E> {
             error!("Trap: {category:?}: {}", decode_latin1(message.as_ref()));
             None
         }
+        SentenceResult::ObjectId { id } => {
+            warn!("Unexpected object id: {id:?}");
+            None
+        }
     }
 }
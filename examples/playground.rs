@@ -3,7 +3,9 @@ use env_logger::{Env, TimestampPrecision};
 use mikrotik_model::ascii::AsciiString;
 use mikrotik_model::model::{InterfaceEthernetCfg, IpAddress};
 use mikrotik_model::model::{ReferenceType, ResourceType};
-use mikrotik_model::resource::{FieldUpdateHandler, RosResource, SentenceResult, SetResource};
+use mikrotik_model::resource::{
+    FieldUpdateHandler, ReplyContext, RosResource, SentenceResult, SetResource,
+};
 use mikrotik_model::value::{KeyValuePair, RosValue};
 use mikrotik_model::{Credentials, MikrotikDevice, ascii};
 use std::borrow::Cow;
@@ -74,7 +76,7 @@ async fn main() -> anyhow::Result<()> {
     let mut stream = device
         .send_simple_command(
             &[b"/", IpAddress::path(), b"/print"],
-            ResourceType::IpAddress,
+            ReplyContext::Resource(ResourceType::IpAddress),
         )
         .await;
     while let Some(result) = stream.next().await {
@@ -92,6 +94,7 @@ async fn main() -> anyhow::Result<()> {
                     .for_each(|warning| println!("warning: {}", warning));
             }
             SentenceResult::Trap { category, message } => {}
+            SentenceResult::ObjectId { id } => println!("id: {id:?}"),
         }
     }
 
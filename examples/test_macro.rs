@@ -4,6 +4,7 @@ use log::{error, info};
 use mikrotik_model::{
     Credentials, MikrotikDevice, ascii,
     ascii::AsciiString,
+    desired_state,
     generator::Generator,
     hwconfig::{ADVERTISE_1G, EthernetNamePattern, generate_ethernet},
     resource::ResourceMutation,
@@ -78,10 +79,15 @@ async fn main() -> anyhow::Result<()> {
     .await?;
     let current_data = DeviceDataCurrent::fetch(&device).await?;
     info!("Current device: {:#?}", current_data);
-    let mut target_data = DeviceDataTarget::detect_device(&device).await?;
-    //let mut target_data = DeviceDataTarget::new(DeviceType::C52iG5haxD2haxD);
+    let target_data = if let Some(desired_state) = std::env::args().nth(1) {
+        desired_state::load_yaml::<DeviceDataTarget>(desired_state)?
+    } else {
+        let mut target_data = DeviceDataTarget::detect_device(&device).await?;
+        //let mut target_data = DeviceDataTarget::new(DeviceType::C52iG5haxD2haxD);
 
-    target_data.set_identity(b"ap-buero");
+        target_data.set_identity(b"ap-buero");
+        target_data
+    };
     let remaining_updates = match target_data.generate_mutations(&current_data) {
         Ok(mutations) => mutations,
         Err(error) => {
@@ -0,0 +1,22 @@
+//! Prints the JSON Schema of `model::Data`, or of a single entity if a path like
+//! `interface/vlan` is given.
+use mikrotik_model_generator::{known_entities, known_enums, schema};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let schema = match std::env::args().nth(1) {
+        None => schema::data_schema(),
+        Some(path) => {
+            let enums = known_enums();
+            match known_entities().find(|entity| entity.path.join("/") == path) {
+                Some(entity) => schema::entity_schema(&entity, &enums),
+                None => {
+                    eprintln!("unknown path: {path}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+    };
+    println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+    ExitCode::SUCCESS
+}
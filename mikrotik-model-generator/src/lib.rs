@@ -10,12 +10,13 @@ use std::{
     vec,
 };
 use syn::{
-    __private::ToTokens, parse_quote, punctuated::Punctuated, token::Comma, ExprMatch, FieldValue,
-    FieldsNamed, Item, Variant,
+    __private::ToTokens, parse_quote, punctuated::Punctuated, token::Comma, Expr, ExprMatch,
+    FieldValue, FieldsNamed, Item, Stmt, Type, Variant,
 };
 
 pub mod macros;
 pub mod model;
+pub mod schema;
 lazy_static! {
     static ref KEYWORDS: HashSet<&'static str> = HashSet::from([
         "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
@@ -34,6 +35,9 @@ pub fn known_entities() -> impl Iterator<Item = Entity> {
     collect_entities(&ROS_MODEL_DIR, &mut entities);
     entities.into_iter()
 }
+pub fn known_enums() -> EnumDescriptions {
+    serde_yaml::from_str(include_str!("../ros_model/enums.yaml")).unwrap()
+}
 fn collect_entities(dir: &Dir, target: &mut Vec<Entity>) {
     target.extend(
         dir.files()
@@ -65,7 +69,7 @@ pub fn generator() -> syn::File {
         parse_quote!(
             use crate::{
                 resource,
-                value::{self, IpOrInterface, ClockFrequency, Id},
+                value::{self, IpOrInterface, ClockFrequency, Id, AsNumber},
                 ascii,
             };
         ),
@@ -82,8 +86,7 @@ pub fn generator() -> syn::File {
             use ipnet::{IpNet, Ipv4Net, Ipv6Net};
         ),
     ];
-    let enums: EnumDescriptions =
-        serde_yaml::from_str(include_str!("../ros_model/enums.yaml")).unwrap();
+    let enums = known_enums();
 
     for item in generate_enums(
         enums
@@ -100,7 +103,20 @@ pub fn generator() -> syn::File {
     let mut outgoing_chain_edges = HashMap::new();
     let mut entries_by_incoming_references = HashMap::new();
 
+    let mut default_value_match: ExprMatch = parse_quote! {match (path, key){}};
+    let mut key_fields_match: ExprMatch = parse_quote! {match path{}};
+    let mut reference_fields_match: ExprMatch = parse_quote! {match path{}};
+
     for entity in known_entities() {
+        default_value_match
+            .arms
+            .extend(entity.generate_default_value_arms());
+        key_fields_match
+            .arms
+            .extend(entity.generate_key_fields_arm());
+        reference_fields_match
+            .arms
+            .extend(entity.generate_reference_fields_arm());
         let (entity_items, enum_fields, references) = entity.generate_code();
         for item in entity_items {
             items.push(item);
@@ -174,12 +190,16 @@ pub fn generator() -> syn::File {
     let mut append_field_match: ExprMatch = parse_quote! {match self{}};
     let mut build_match: ExprMatch = parse_quote! {match self{}};
     let mut resource2type_match: ExprMatch = parse_quote! {match self{}};
+    let mut type2path_match: ExprMatch = parse_quote! {match self{}};
     let mut resource_ref_cloned_type_match: ExprMatch = parse_quote! {match self{}};
     let mut data_fields = FieldsNamed {
         brace_token: Default::default(),
         named: Default::default(),
     };
     let mut data_loader_fields: Punctuated<FieldValue, Comma> = Punctuated::new();
+    let mut data_entry_values_match: ExprMatch = parse_quote! {match resource_type{}};
+    let mut data_insert_match: ExprMatch = parse_quote! {match resource{}};
+    let mut data_full_config_stmts: Vec<Stmt> = Vec::new();
 
     for (field, _, _) in all_generated_types {
         /*let length = incoming_references
@@ -215,23 +235,91 @@ pub fn generator() -> syn::File {
         resource2type_match
             .arms
             .push(parse_quote! {Self::#name(_)=>ResourceType::#name});
+        type2path_match
+            .arms
+            .push(parse_quote! {Self::#name=><#data_type as resource::RosResource>::path()});
         resource_ref_cloned_type_match
             .arms
             .push(parse_quote! {&Self::#name(r)=>Resource::#name(r.clone())});
-        if field.can_update {
-            let name = field.field_name;
-            data_fields
-                .named
-                .push(parse_quote!(pub #name: Vec<#data_type>));
-            data_loader_fields.push(parse_quote! {#name:crate::util::default_if_missing(<#data_type as resource::KeyedResource>::fetch_all(device).await)?});
-        } else if field.can_add {
+        if !matches!(data_type, Type::Tuple(_)) {
+            items.push(parse_quote! {
+                impl serde::Serialize for #data_type {
+                    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                        resource::serialize_ros_fields(self, serializer)
+                    }
+                }
+            });
+            items.push(parse_quote! {
+                impl<'de> serde::Deserialize<'de> for #data_type {
+                    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                        resource::deserialize_ros_fields::<Self, #builder_type, D>(deserializer)
+                    }
+                }
+            });
+        }
+        let type_name = name.clone();
+        if field.can_update || field.can_add {
             let name = field.field_name;
+            data_entry_values_match.arms.push(parse_quote! {
+                ResourceType::#type_name => {
+                    let (key, value) = key?;
+                    self.#name.iter().enumerate().find_map(|(idx, entry)| {
+                        let values = resource::CfgResource::field_values(entry).collect::<Vec<_>>();
+                        values
+                            .iter()
+                            .any(|kv| kv.key == key && kv.value.as_ref() == value)
+                            .then_some((Some(idx), values))
+                    })
+                }
+            });
+            data_insert_match.arms.push(parse_quote! {
+                Resource::#type_name(value) => {
+                    match position {
+                        Some(idx) => self.#name[idx] = value,
+                        None => self.#name.push(value),
+                    }
+                    true
+                }
+            });
+            if field.can_update {
+                let create: Option<Expr> = field.can_add.then(|| {
+                    parse_quote! {mutations.push(resource::Creatable::calculate_create(entry))}
+                });
+                let create = create.into_iter();
+                data_full_config_stmts.push(parse_quote! {
+                    for entry in &self.#name {
+                        if let Some(current) = base.#name.iter().find(|current| resource::KeyedResource::key_value(*current) == resource::KeyedResource::key_value(entry)) {
+                            mutations.push(resource::Updatable::calculate_update(entry, current));
+                        } #(else { #create; })*
+                    }
+                });
+            } else {
+                data_full_config_stmts.push(parse_quote! {
+                    mutations.extend(self.#name.iter().map(resource::Creatable::calculate_create));
+                });
+            }
             data_fields
                 .named
                 .push(parse_quote!(pub #name: Vec<#data_type>));
-            data_loader_fields.push(parse_quote! {#name:Default::default()});
+            if field.can_update {
+                data_loader_fields.push(parse_quote! {#name:crate::util::default_if_missing(<#data_type as resource::KeyedResource>::fetch_all(device).await)?});
+            } else {
+                data_loader_fields.push(parse_quote! {#name:Default::default()});
+            }
         } else if field.is_single {
             let name = field.field_name;
+            data_entry_values_match.arms.push(parse_quote! {
+                ResourceType::#type_name => Some((None, resource::CfgResource::field_values(&self.#name).collect()))
+            });
+            data_insert_match.arms.push(parse_quote! {
+                Resource::#type_name(value) => {
+                    self.#name = value;
+                    true
+                }
+            });
+            data_full_config_stmts.push(parse_quote! {
+                mutations.push(resource::generate_single_update(&base.#name, &self.#name));
+            });
             data_fields.named.push(parse_quote!(pub #name: #data_type));
             data_loader_fields.push(
                 parse_quote! {#name:<#data_type as resource::SingleResource>::fetch(device).await?.ok_or(resource::Error::ErrorFetchingSingleItem)?},
@@ -260,6 +348,9 @@ pub fn generator() -> syn::File {
             pub fn create_builder(&self)->ResourceBuilder{
                 #resource_init_match
             }
+            pub fn path(&self)->&'static [u8]{
+                #type2path_match
+            }
         }
     });
     items.push(parse_quote!(
@@ -292,15 +383,46 @@ pub fn generator() -> syn::File {
     for (name, _) in known_references {
         reference_enum_variants.push(parse_quote!(#name));
     }
+    reference_enum_variants.push(parse_quote!(
+        /// `.id` assigned to an entry added within the same set of mutations
+        ObjectId
+    ));
     items.push(parse_quote!(
         #[derive(Copy,Debug,Clone,PartialEq, Hash, Eq)]
         pub enum ReferenceType {#reference_enum_variants}
     ));
 
     items.push(parse_quote!(
-        #[derive(Debug,Clone,PartialEq, Default)]
+        #[derive(Debug,Clone,PartialEq, Default, serde::Serialize, serde::Deserialize)]
+        #[serde(default)]
         pub struct Data #data_fields
     ));
+    default_value_match.arms.push(parse_quote! {_ => None});
+    items.push(parse_quote!(
+        /// Value RouterOS assigns to `key` of an entry at `path` unless it is set explicitly,
+        /// as declared with `default=` in the ros_model
+        pub fn default_value(path: &[u8], key: &[u8]) -> Option<std::borrow::Cow<'static, [u8]>> {
+            #default_value_match
+        }
+    ));
+    key_fields_match.arms.push(parse_quote! {_ => &[]});
+    items.push(parse_quote!(
+        /// Names of the fields identifying an entry at `path`, empty if it is only known by its `.id`
+        pub fn key_fields(path: &[u8]) -> &'static [&'static [u8]] {
+            #key_fields_match
+        }
+    ));
+    reference_fields_match.arms.push(parse_quote! {_ => &[]});
+    items.push(parse_quote!(
+        /// Names of the fields at `path` referring to another entry, with the type of the reference
+        pub fn reference_fields(path: &[u8]) -> &'static [(&'static [u8], ReferenceType)] {
+            #reference_fields_match
+        }
+    ));
+    data_entry_values_match
+        .arms
+        .push(parse_quote! {_ => None});
+    data_insert_match.arms.push(parse_quote! {_ => false});
     items.push(parse_quote!(
 
         impl Data {
@@ -309,6 +431,30 @@ pub fn generator() -> syn::File {
                     #data_loader_fields
                 })
             }
+            /// Field values of the stored entry with the given `key` and value, or of the single
+            /// resource, together with the position of the entry in its list
+            pub fn entry_values(
+                &self,
+                resource_type: ResourceType,
+                key: Option<(&[u8], &[u8])>,
+            ) -> Option<(Option<usize>, Vec<value::KeyValuePair<'_>>)> {
+                #data_entry_values_match
+            }
+            /// Stores `resource`, replacing the list entry at `position` or the single resource.
+            /// Returns `false` if there is no field for its type.
+            pub fn insert(&mut self, resource: Resource, position: Option<usize>) -> bool {
+                #data_insert_match
+            }
+            /// Mutations turning `base`, e.g. the state after a reset, into this configuration.
+            ///
+            /// Single resources and keyed entries found in `base` are updated, other entries are
+            /// created where the resource allows it. Entries keyed by a read-only field which are
+            /// missing in `base` are skipped.
+            pub fn full_config_mutations<'a>(&'a self, base: &'a Data) -> Vec<resource::ResourceMutation<'a>> {
+                let mut mutations = Vec::new();
+                #(#data_full_config_stmts)*
+                mutations
+            }
         }
     ));
 
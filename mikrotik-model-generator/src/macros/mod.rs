@@ -1,15 +1,15 @@
-use crate::{cleanup_field_name, known_entities, name2ident};
+use crate::{cleanup_field_name, known_entities, model::Entity, name2ident};
 use convert_case::{Case, Casing};
 use darling::{
     ast::NestedMeta,
     util::{PathList, SpannedValue},
     Error, FromMeta,
 };
-use proc_macro2::{Ident, TokenStream};
+use proc_macro2::{Ident, Span, TokenStream};
 use std::collections::HashMap;
 use syn::{
-    __private::ToTokens, parse_quote, spanned::Spanned, Block, Expr, ExprStruct, Fields, ItemImpl,
-    ItemStruct, PatTuple, Stmt, TypeTuple,
+    __private::ToTokens, parse_quote, spanned::Spanned, Block, Expr, ExprStruct, ExprTuple, Fields,
+    ItemImpl, ItemStruct, LitByteStr, PatTuple, Path, Stmt, TypeTuple,
 };
 
 #[cfg(test)]
@@ -43,14 +43,24 @@ pub fn mikrotik_model(item: TokenStream) -> Result<TokenStream, Error> {
         struct #current_struct_name{}
     };
     let mut current_fetch_init: ExprStruct = parse_quote! {Self{}};
+    let mut current_from_data_init: ExprStruct = parse_quote! {Self{}};
     let mut target_struct: ItemStruct = parse_quote! {
         #[derive(Clone, Debug, PartialEq)]
         struct #target_struct_name{}
     };
+    let mut file_struct: ItemStruct = parse_quote! {
+        #[derive(mikrotik_model::serde::Deserialize)]
+        #[serde(crate = "mikrotik_model::serde", deny_unknown_fields)]
+        struct File{}
+    };
+    let mut target_from_file_init: ExprStruct = parse_quote! {Self{}};
+    let mut collections: Vec<Expr> = Vec::new();
     let mut accumulator = Error::accumulator();
     let mut generate_mutations_expr: Option<Expr> = None;
-    match (&mut current_struct.fields, &mut target_struct.fields) {
-        (Fields::Named(current_struct_fields), Fields::Named(target_struct_fields)) => {
+    let mut inverse_mutation_expr: Option<Expr> = None;
+    let mut has_renames = false;
+    match (&mut current_struct.fields, &mut target_struct.fields, &mut file_struct.fields) {
+        (Fields::Named(current_struct_fields), Fields::Named(target_struct_fields), Fields::Named(file_struct_fields)) => {
             for (field, f_type) in params.fields {
                 let field_name = Ident::new(
                     cleanup_field_name(field.to_string().as_str())
@@ -58,6 +68,7 @@ pub fn mikrotik_model(item: TokenStream) -> Result<TokenStream, Error> {
                         .as_str(),
                     field.span(),
                 );
+                let field_name_str = field_name.to_string();
                 match f_type {
                     TypeEntry::Single(single_type) => {
                         match known_structs.get(single_type.as_str()) {
@@ -78,6 +89,8 @@ pub fn mikrotik_model(item: TokenStream) -> Result<TokenStream, Error> {
                                         entry.path.join("/")
                                     );
                                     current_fetch_init.fields.push(parse_quote! {#field_name: <mikrotik_model::model::#field_type as mikrotik_model::resource::SingleResource>::fetch(device).await?.expect(#not_found_error_msg)});
+                                    let data_field = entry.struct_ident_cfg_field();
+                                    current_from_data_init.fields.push(parse_quote! {#field_name: data.#data_field.clone()});
                                     generate_mutations_expr = chain(
                                         generate_mutations_expr,
                                         parse_quote! {Some(mikrotik_model::resource::generate_single_update(&from.#field_name,&self.#field_name)).into_iter()},
@@ -85,6 +98,15 @@ pub fn mikrotik_model(item: TokenStream) -> Result<TokenStream, Error> {
                                     target_struct_fields
                                         .named
                                         .push(parse_quote!(#field_name:mikrotik_model::model::#field_type));
+                                    file_struct_fields
+                                        .named
+                                        .push(parse_quote!(#field_name:mikrotik_model::model::#field_type));
+                                    target_from_file_init.fields.push(parse_quote! {#field_name: file.#field_name});
+                                    collections.push(collection_expr(&field_name_str, entry, None));
+                                    inverse_mutation_expr = or_else(
+                                        inverse_mutation_expr,
+                                        parse_quote! {mutation.inverse(&self.#field_name)},
+                                    );
                                 } else {
                                     accumulator.push(
                                         Error::custom("type is not single")
@@ -135,19 +157,36 @@ pub fn mikrotik_model(item: TokenStream) -> Result<TokenStream, Error> {
                                     }
                                 }
 
+                                let key_names = key_fields.iter().map(|f| f.name.as_ref()).collect::<Vec<_>>();
+                                collections.push(collection_expr(&field_name_str, entry, Some(&key_names)));
                                 let current_field_type = entry.id_struct_type(id_field);
+                                let generate_fn: Ident = if entry.is_ordered {
+                                    parse_quote!(generate_ordered_by_id)
+                                } else {
+                                    parse_quote!(generate_add_update_remove_by_id)
+                                };
                                 current_struct_fields
                                     .named
                                     .push(parse_quote! {#field_name: Box<[mikrotik_model::model::#current_field_type]>});
                                 current_fetch_init.fields.push(parse_quote! {#field_name: <mikrotik_model::model::#current_field_type as mikrotik_model::resource::KeyedResource>::fetch_all(device).await?});
+                                let data_field = entry.id_struct_ident_field(id_field);
+                                current_from_data_init.fields.push(parse_quote! {#field_name: data.#data_field.clone().into_boxed_slice()});
+                                inverse_mutation_expr = or_else(
+                                    inverse_mutation_expr,
+                                    parse_quote! {mutation.inverse_by_key(&self.#field_name)},
+                                );
                                 if key_fields.is_empty() {
                                     target_struct_fields
                                         .named
                                         .push(parse_quote!(#field_name:Vec<mikrotik_model::model::#field_type>));
+                                    file_struct_fields
+                                        .named
+                                        .push(parse_quote!(#field_name:Vec<mikrotik_model::model::#field_type>));
+                                    target_from_file_init.fields.push(parse_quote! {#field_name: file.#field_name});
                                     generate_mutations_expr = chain(
                                         generate_mutations_expr,
                                         parse_quote! {
-                                            mikrotik_model::resource::generate_add_update_remove_by_id(&from.#field_name,
+                                            mikrotik_model::resource::#generate_fn(&from.#field_name,
                                                 self.#field_name.iter().map(std::borrow::Cow::<mikrotik_model::model::#field_type>::Borrowed)
                                             )
                                         },
@@ -162,12 +201,14 @@ pub fn mikrotik_model(item: TokenStream) -> Result<TokenStream, Error> {
                                     let mut generate_block: Block = parse_quote! {{
                                        let mut entry=entry.clone();
                                     }};
+                                    let mut key_expr: ExprTuple = parse_quote!(());
 
                                     for field in key_fields {
                                         let ty = entry.struct_field_type(field);
                                         key_type.elems.push(ty);
                                         let name = field.generate_field_name();
                                         key_values.elems.push(parse_quote! {#name});
+                                        key_expr.elems.push(parse_quote! {entry.#name.clone()});
                                         generate_block
                                             .stmts
                                             .push(parse_quote! {entry.#name = #name.clone();});
@@ -179,10 +220,16 @@ pub fn mikrotik_model(item: TokenStream) -> Result<TokenStream, Error> {
                                     target_struct_fields.named.push(
                                         parse_quote!(#field_name:std::collections::BTreeMap<#key_type,mikrotik_model::model::#field_type>),
                                     );
+                                    file_struct_fields
+                                        .named
+                                        .push(parse_quote!(#field_name:Vec<mikrotik_model::model::#field_type>));
+                                    target_from_file_init.fields.push(parse_quote! {
+                                        #field_name: mikrotik_model::desired_state::collect_unique::<_, _, D::Error>(#field_name_str, file.#field_name.into_iter().map(|entry|(#key_expr, entry)))?
+                                    });
                                     generate_mutations_expr = chain(
                                         generate_mutations_expr,
                                         parse_quote! {
-                                            mikrotik_model::resource::generate_add_update_remove_by_id(&from.#field_name,
+                                            mikrotik_model::resource::#generate_fn(&from.#field_name,
                                                 self.#field_name.iter().map(|(#key_values,entry)|#generate_block).map(std::borrow::Cow::<mikrotik_model::model::#field_type>::Owned)
                                             )
                                         },
@@ -195,7 +242,7 @@ pub fn mikrotik_model(item: TokenStream) -> Result<TokenStream, Error> {
                             }
                         }
                     },
-                    TypeEntry::ByKey { path, key } => match known_structs.get(path.as_str()) {
+                    TypeEntry::ByKey { path, key, rename } => match known_structs.get(path.as_str()) {
                         None => {
                             accumulator.push(
                                 Error::custom("mikrotik path not found").with_span(&path.span()),
@@ -213,9 +260,27 @@ pub fn mikrotik_model(item: TokenStream) -> Result<TokenStream, Error> {
                                     .named
                                     .push(parse_quote! {#field_name: Box<[mikrotik_model::model::#field_type]>});
                                 current_fetch_init.fields.push(parse_quote! {#field_name: <mikrotik_model::model::#field_type as mikrotik_model::resource::KeyedResource>::fetch_all(device).await?});
+                                let data_field = entry.id_struct_ident_field(key_field);
+                                current_from_data_init.fields.push(parse_quote! {#field_name: data.#data_field.clone().into_boxed_slice()});
+                                inverse_mutation_expr = or_else(
+                                    inverse_mutation_expr,
+                                    parse_quote! {mutation.inverse_by_key(&self.#field_name)},
+                                );
                                 let key_type = entry.struct_field_type(key_field);
                                 let cfg_type = entry.struct_type_cfg();
                                 let key_field_name = key_field.generate_field_name();
+                                file_struct_fields
+                                    .named
+                                    .push(parse_quote!(#field_name:Vec<mikrotik_model::model::#field_type>));
+                                collections.push(collection_expr(&field_name_str, entry, Some(&[key_field.name.as_ref()])));
+                                let file_entry_expr: Expr = if key_field.is_read_only {
+                                    parse_quote! {(entry.#key_field_name, entry.data)}
+                                } else {
+                                    parse_quote! {(entry.0.#key_field_name.clone(), entry)}
+                                };
+                                target_from_file_init.fields.push(parse_quote! {
+                                    #field_name: mikrotik_model::desired_state::collect_unique::<_, _, D::Error>(#field_name_str, file.#field_name.into_iter().map(|entry|#file_entry_expr))?
+                                });
                                 if key_field.is_read_only {
                                     target_struct_fields.named.push(parse_quote!(#field_name:std::collections::BTreeMap<#key_type,mikrotik_model::model::#cfg_type>));
                                 } else {
@@ -244,7 +309,22 @@ pub fn mikrotik_model(item: TokenStream) -> Result<TokenStream, Error> {
 
                                 generate_mutations_expr = chain(
                                     generate_mutations_expr,
-                                    if entry.can_add {
+                                    if let Some(rename) = rename {
+                                        if !entry.can_add {
+                                            accumulator.push(
+                                                Error::custom("rename needs a resource which can be added")
+                                                    .with_span(&rename.span()),
+                                            );
+                                        }
+                                        has_renames = true;
+                                        parse_quote! {
+                                            {
+                                                let (renames, mutations) = mikrotik_model::resource::generate_add_update_remove_by_key_with_renames(&from.#field_name,#iter_expr, #rename);
+                                                renamed.append(renames);
+                                                mutations
+                                            }
+                                        }
+                                    } else if entry.can_add {
                                         parse_quote! {
                                             mikrotik_model::resource::generate_add_update_remove_by_key(&from.#field_name,#iter_expr)
                                         }
@@ -274,21 +354,70 @@ pub fn mikrotik_model(item: TokenStream) -> Result<TokenStream, Error> {
             async fn fetch(device: &MikrotikDevice) -> Result<Self, mikrotik_model::resource::Error> {
                 Ok(#current_fetch_init)
             }
+            /// Takes the current state from `data`, e.g. of a stored snapshot
+            pub fn from_data(data: &mikrotik_model::model::Data) -> Self {
+                #current_from_data_init
+            }
         }
     };
 
     stream.extend(current_struct.to_token_stream());
     stream.extend(current_impl.to_token_stream());
+    if let Some(inverse_mutation) = inverse_mutation_expr {
+        let rollback_impl: ItemImpl = parse_quote! {
+            impl mikrotik_model::resource::RollbackSource for #current_struct_name {
+                fn inverse_mutation(&self, mutation: &mikrotik_model::resource::ResourceMutation) -> Option<mikrotik_model::resource::ResourceMutation<'_>> {
+                    #inverse_mutation
+                }
+            }
+        };
+        stream.extend(rollback_impl.to_token_stream());
+    }
     if let Some(mutations) = generate_mutations_expr {
         stream.extend(target_struct.to_token_stream());
+        let generate_mutations_body: Block = if has_renames {
+            parse_quote! {{
+                let mut renamed = mikrotik_model::resource::RenamedReferences::default();
+                let mut mutations: Box<[mikrotik_model::resource::ResourceMutation<'a>]> = #mutations.collect();
+                for mutation in mutations.iter_mut() {
+                    renamed.rewrite_depends(mutation);
+                }
+                Ok(mutations)
+            }}
+        } else {
+            parse_quote! {{
+                Ok(#mutations.collect())
+            }}
+        };
         let target_impl: ItemImpl = parse_quote! {
             impl #target_struct_name {
-                fn generate_mutations<'a>(&'a self, from: &'a #current_struct_name)->Result<Box<[mikrotik_model::resource::ResourceMutation<'a>]>, mikrotik_model::resource::ResourceMutationError> {
-                    Ok(#mutations.collect())
+                fn generate_mutations<'a>(&'a self, from: &'a #current_struct_name)->Result<Box<[mikrotik_model::resource::ResourceMutation<'a>]>, mikrotik_model::resource::ResourceMutationError> #generate_mutations_body
+                /// Refetches the device state after applying and reports everything still differing from `self`.
+                pub async fn check_convergence(&self, device: &MikrotikDevice) -> Result<mikrotik_model::convergence::ConvergenceReport, mikrotik_model::convergence::ConvergenceError> {
+                    let current = #current_struct_name::fetch(device).await?;
+                    let mutations = self.generate_mutations(&current).map_err(Box::new)?;
+                    Ok(mikrotik_model::convergence::ConvergenceReport::from_mutations(mutations.iter()))
                 }
             }
         };
         stream.extend(target_impl.to_token_stream());
+        let deserialize_impl: ItemImpl = parse_quote! {
+            /// Reads the desired state from a file, see `mikrotik_model::desired_state::load_yaml`.
+            impl<'de> mikrotik_model::serde::Deserialize<'de> for #target_struct_name {
+                fn deserialize<D: mikrotik_model::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    #file_struct
+                    let file = <File as mikrotik_model::serde::Deserialize>::deserialize(deserializer)?;
+                    Ok(#target_from_file_init)
+                }
+            }
+        };
+        stream.extend(deserialize_impl.to_token_stream());
+        let layered_impl: ItemImpl = parse_quote! {
+            impl mikrotik_model::profile::LayeredTarget for #target_struct_name {
+                const COLLECTIONS: &'static [mikrotik_model::profile::Collection] = &[#(#collections),*];
+            }
+        };
+        stream.extend(layered_impl.to_token_stream());
     }
     if let Some(detect_method) = params.detect {
         let target_impl: ItemImpl = parse_quote! {
@@ -311,6 +440,21 @@ fn derive_key_name(key: &Ident) -> String {
     key_name
 }
 
+fn collection_expr(field: &str, entry: &Entity, keys: Option<&[&str]>) -> Expr {
+    let resource = LitByteStr::new(entry.path.join("/").as_bytes(), Span::call_site());
+    let keys: Expr = match keys {
+        None => parse_quote! {None},
+        Some(keys) => parse_quote! {Some(&[#(#keys),*])},
+    };
+    parse_quote! {
+        mikrotik_model::profile::Collection {
+            field: #field,
+            resource: #resource,
+            keys: #keys,
+        }
+    }
+}
+
 fn chain(chain: Option<Expr>, item: Expr) -> Option<Expr> {
     if let Some(expr_before) = chain {
         Some(parse_quote! {#expr_before.chain(#item)})
@@ -318,6 +462,13 @@ fn chain(chain: Option<Expr>, item: Expr) -> Option<Expr> {
         Some(parse_quote! {#item})
     }
 }
+fn or_else(chain: Option<Expr>, item: Expr) -> Option<Expr> {
+    if let Some(expr_before) = chain {
+        Some(parse_quote! {#expr_before.or_else(|| #item)})
+    } else {
+        Some(parse_quote! {#item})
+    }
+}
 #[derive(FromMeta, Debug)]
 struct MikrotikModelParams {
     name: Ident,
@@ -335,5 +486,8 @@ enum TypeEntry {
     ByKey {
         path: SpannedValue<String>,
         key: Ident,
+        /// `fn(&Current, &Target) -> bool` detecting entries renamed by a changed key
+        #[darling(default)]
+        rename: Option<Path>,
     },
 }
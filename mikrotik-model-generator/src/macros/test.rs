@@ -34,3 +34,35 @@ fn test_routerboard() {
     let file = syn::parse2(result).unwrap();
     println!("{}", prettyplease::unparse(&file));
 }
+
+#[test]
+fn test_rename() {
+    let attr: TokenStream = parse_quote! {
+        name=DeviceData,
+        fields(
+            bridge(by_key(path="interface/bridge",key=name,rename=same_bridge)),
+        )
+    };
+    let result = mikrotik_model(attr).expect("failed to run model");
+    let file: syn::File = syn::parse2(result).unwrap();
+    let code = prettyplease::unparse(&file);
+    assert!(code.contains("generate_add_update_remove_by_key_with_renames"));
+    assert!(code.contains("same_bridge"));
+    assert!(code.contains("rewrite_depends"));
+}
+
+#[test]
+fn test_bgp_by_id() {
+    let attr: TokenStream = parse_quote! {
+        name=DeviceData,
+        fields(
+            bgp_template(by_id(path="routing/bgp/template",keys(name))),
+            bgp_connection(by_id(path="routing/bgp/connection",keys(name))),
+            bgp_vpn(by_id(path="routing/bgp/vpn",keys(name))),
+        )
+    };
+    let result = mikrotik_model(attr).expect("failed to run model");
+    let file: syn::File = syn::parse2(result).unwrap();
+    let code = prettyplease::unparse(&file);
+    assert!(code.contains("RoutingBgpVpnById"));
+}
@@ -8,8 +8,8 @@ use syn::{
     parse_quote,
     punctuated::Punctuated,
     token::{Colon, Comma},
-    Block, Expr, ExprArray, ExprField, ExprMatch, ExprStruct, FieldValue, FieldsNamed, FnArg,
-    ImplItem, Item, ItemFn, ItemImpl, Member, Path, PathSegment, Token, Type, TypePath,
+    Arm, Block, Expr, ExprArray, ExprField, ExprMatch, ExprStruct, FieldValue, FieldsNamed, FnArg,
+    ImplItem, ImplItemFn, Item, ItemFn, ItemImpl, Member, Path, PathSegment, Token, Type, TypePath,
 };
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
@@ -24,6 +24,7 @@ pub struct Entity {
     pub is_single: bool,
     pub can_add: bool,
     pub no_default: bool,
+    pub is_ordered: bool,
 }
 
 pub struct RosTypeEntry {
@@ -59,6 +60,7 @@ impl Entity {
                         is_single: false,
                         can_add: false,
                         no_default: false,
+                        is_ordered: false,
                     };
                     for param in params.split(';') {
                         if let Some((key, value)) = param.split_once('=') {
@@ -73,6 +75,7 @@ impl Entity {
                                 "can-add" => entity.can_add = true,
                                 "is-single" => entity.is_single = true,
                                 "no-default" => entity.no_default = true,
+                                "ordered" => entity.is_ordered = true,
                                 "" => {}
                                 _ => panic!("Unknown param: {param}"),
                             }
@@ -88,6 +91,7 @@ impl Entity {
                         is_single: false,
                         can_add: false,
                         no_default: false,
+                        is_ordered: false,
                     }
                 };
                 if let Some(entity) = current_entity.replace(entity) {
@@ -102,6 +106,7 @@ impl Entity {
                     is_single: true,
                     can_add: false,
                     no_default: false,
+                    is_ordered: false,
                 }) {
                     collected_entities.push(entity);
                 }
@@ -114,6 +119,7 @@ impl Entity {
                     is_single: false,
                     can_add: true,
                     no_default: false,
+                    is_ordered: false,
                 }) {
                     collected_entities.push(entity);
                 }
@@ -139,6 +145,9 @@ impl Entity {
         if self.is_single {
             write!(writer, "is-single;")?;
         }
+        if self.is_ordered {
+            write!(writer, "ordered;")?;
+        }
         writer.write_char('\n')?;
         for field in &self.fields {
             field.write_field_line(writer)?;
@@ -164,6 +173,10 @@ impl Entity {
         if has_cfg_struct {
             items.push(self.create_cfg_struct());
             items.push(self.create_cfg_builder_struct());
+            items.push(Self::generate_serialize_fields(
+                self.struct_type_cfg(),
+                self.modifiable_fields_iterator(),
+            ));
             enum_entries.push(self.create_cfg_enum_entry());
             items.push(self.generate_has_reference_for_cfg_struct());
             items.push(self.generate_deserialize_for_cfg_struct());
@@ -187,6 +200,7 @@ impl Entity {
                     items.push(self.generate_deserialize_for_id(id_field));
                     items.push(self.generate_ros_resource_for_id(id_field));
                     enum_entries.push(self.create_id_enum_entry(id_field));
+                    items.push(self.generate_serialize_fields_for_id(id_field));
                     if id_field.is_read_only {
                         items.push(self.generate_id_struct_external(id_field));
                         items.push(self.generate_id_builder_external(id_field));
@@ -213,6 +227,10 @@ impl Entity {
                         items.push(self.generate_ros_for_id_combined(id_field));
                         items.push(self.generate_deserialize_builder_for_id_combined(id_field));
                         items.push(self.generate_keyed_for_id_combined(id_field));
+                        items.push(self.generate_updateable_for_id_combined(id_field, &self.id_struct_type(id_field)));
+                        if id_field.is_read_only {
+                            items.push(self.generate_updateable_for_id_combined(id_field, &self.struct_type_cfg()));
+                        }
                         enum_entries.push(self.create_id_enum_entry_combined(id_field));
                     }
                 }
@@ -227,6 +245,10 @@ impl Entity {
         if has_readonly_fields {
             items.push(self.create_status_struct());
             items.push(self.create_status_builder_struct());
+            items.push(Self::generate_serialize_fields(
+                self.struct_status_type(),
+                self.read_only_fields_iterator(),
+            ));
             items.push(self.generate_has_reference_for_status_struct());
             items.push(self.generate_deserialize_for_status_struct());
             items.push(self.create_ros_resource_for_status());
@@ -239,6 +261,10 @@ impl Entity {
         if has_monitor_fields {
             items.push(self.create_monitor_struct());
             items.push(self.create_monitor_builder_struct());
+            items.push(Self::generate_serialize_fields(
+                self.struct_monitor_type(),
+                self.monitor_fields_iterator(),
+            ));
             items.push(self.generate_has_reference_for_monitor_struct());
             items.push(self.generate_deserialize_for_monitor_struct());
             items.push(self.create_ros_resource_for_monitor());
@@ -249,6 +275,7 @@ impl Entity {
             enum_entries.push(self.create_enum_entry());
             items.push(self.generate_combined_struct());
             items.push(self.generate_combined_builder_struct());
+            items.push(self.generate_serialize_fields_for_combined_struct());
             items.push(self.generate_has_reference_for_combined_struct());
             items.push(self.generate_deserialize_for_combined_struct());
             items.push(self.generate_combined_deserialize_builder());
@@ -287,10 +314,13 @@ impl Entity {
     fn referencing_fields(&self) -> impl Iterator<Item = (Ident, bool, &Field)> {
         self.fields
             .iter()
-            .filter_map(|field| match &field.reference {
-                Reference::None => None,
-                Reference::IsReference(r) => Some((crate::name2ident(r.as_ref()), false, field)),
-                Reference::RefereesTo(r) => Some((crate::name2ident(r.as_ref()), true, field)),
+            .flat_map(|field| match &field.reference {
+                Reference::None => Vec::new(),
+                Reference::IsReference(names) => names
+                    .iter()
+                    .map(|r| (crate::name2ident(r.as_ref()), false, field))
+                    .collect(),
+                Reference::RefereesTo(r) => vec![(crate::name2ident(r.as_ref()), true, field)],
             })
     }
 
@@ -368,6 +398,91 @@ impl Entity {
         }
     }
 
+    fn generate_serialize_fields_for_combined_struct(&self) -> Item {
+        let struct_ident = self.struct_type();
+        let field_names_fn = Self::field_names_fn(self.modifiable_fields_iterator().chain(self.read_only_fields_iterator()));
+        parse_quote! {
+            impl resource::SerializeRosFields for #struct_ident {
+                #field_names_fn
+                fn serialize_fields<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> {
+                    self.cfg.serialize_fields(map)?;
+                    self.status.serialize_fields(map)
+                }
+            }
+        }
+    }
+
+    fn field_names_fn<'a>(fields: impl Iterator<Item = &'a Field>) -> ImplItemFn {
+        let names = fields.map(|field| Literal::string(&field.name));
+        parse_quote! {
+            fn field_names() -> &'static [&'static str] {
+                &[#(#names),*]
+            }
+        }
+    }
+
+    fn generate_serialize_fields<'a>(struct_type: Type, fields: impl Iterator<Item = &'a Field>) -> Item {
+        let fields: Vec<_> = fields.collect();
+        let field_names_fn = Self::field_names_fn(fields.iter().copied());
+        let mut stmts: Vec<Stmt> = Vec::new();
+        for field in fields {
+            let field_name = field.generate_field_name();
+            let attribute_name = field.attribute_name();
+            stmts.push(if field.is_optional {
+                parse_quote! {
+                    if let Some(value) = &self.#field_name {
+                        resource::serialize_ros_field(map, #attribute_name, value)?;
+                    }
+                }
+            } else {
+                parse_quote! {
+                    resource::serialize_ros_field(map, #attribute_name, &self.#field_name)?;
+                }
+            });
+        }
+        let map = if stmts.is_empty() {
+            Ident::new("_map", Span::call_site())
+        } else {
+            Ident::new("map", Span::call_site())
+        };
+        parse_quote! {
+            impl resource::SerializeRosFields for #struct_type {
+                #field_names_fn
+                fn serialize_fields<M: serde::ser::SerializeMap>(&self, #map: &mut M) -> Result<(), M::Error> {
+                    #(#stmts)*
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    fn generate_serialize_fields_for_id(&self, id_field: &Field) -> Item {
+        let id_struct_ident = self.id_struct_type(id_field);
+        let field_names_fn = if id_field.is_read_only {
+            Self::field_names_fn(std::iter::once(id_field).chain(self.modifiable_fields_iterator()))
+        } else {
+            Self::field_names_fn(self.modifiable_fields_iterator())
+        };
+        let body: Block = if id_field.is_read_only {
+            let id_field_name = id_field.generate_field_name();
+            let attribute_name = id_field.attribute_name();
+            parse_quote! {{
+                resource::serialize_ros_field(map, #attribute_name, &self.#id_field_name)?;
+                self.data.serialize_fields(map)
+            }}
+        } else {
+            parse_quote! {{
+                self.0.serialize_fields(map)
+            }}
+        };
+        parse_quote! {
+            impl resource::SerializeRosFields for #id_struct_ident {
+                #field_names_fn
+                fn serialize_fields<M: serde::ser::SerializeMap>(&self, map: &mut M) -> Result<(), M::Error> #body
+            }
+        }
+    }
+
     fn generate_keyed_for_id_combined(&self, id_field: &Field) -> Item {
         let id_field_type = self.struct_field_type(id_field);
         let field_name = id_field.attribute_name();
@@ -375,6 +490,7 @@ impl Entity {
         let struct_ident_cfg = self.struct_ident_cfg();
         let id_struct_ident = self.id_struct_type(id_field);
         let filter = self.dynamic_filter();
+        let user_managed_fn = self.user_managed_fn();
 
         let item = parse_quote! {
             impl resource::KeyedResource for (#id_struct_ident, #struct_ident_status) {
@@ -392,11 +508,26 @@ impl Entity {
                     self.0.value()
                 }
                 #filter
+                #user_managed_fn
             }
         };
         item
     }
 
+    /// lets the diff functions take the entries together with their state, so they can skip
+    /// the ones which are not user managed
+    fn generate_updateable_for_id_combined(&self, id_field: &Field, target: &Type) -> Item {
+        let struct_ident_status = self.struct_status_type();
+        let id_struct_ident = self.id_struct_type(id_field);
+        parse_quote! {
+            impl resource::Updatable<(#id_struct_ident, #struct_ident_status)> for #target {
+                fn calculate_update<'a>(&'a self, from: &'a (#id_struct_ident, #struct_ident_status)) -> resource::ResourceMutation<'a> {
+                    resource::Updatable::<#id_struct_ident>::calculate_update(self, &from.0)
+                }
+            }
+        }
+    }
+
     fn generate_deserialize_for_id_combined(&self, id_field: &Field) -> Item {
         Self::generate_deserialize(
             self.data_tuples_for_id_combined(id_field),
@@ -471,6 +602,12 @@ impl Entity {
                 ) -> impl Iterator<Item = value::KeyValuePair<'a>> {
                     self.0.changed_values(&before.0)
                 }
+                fn unset_fields(&self, before: &Self) -> impl Iterator<Item = &'static [u8]> {
+                    self.0.unset_fields(&before.0)
+                }
+                fn field_values(&self) -> impl Iterator<Item = value::KeyValuePair<'_>> {
+                    self.0.field_values()
+                }
             }
         }
     }
@@ -483,13 +620,18 @@ impl Entity {
         parse_quote! {
             impl resource::Updatable<#id_struct_ident> for #id_struct_ident {
                 fn calculate_update<'a>(&'a self, from: &'a Self) -> resource::ResourceMutation<'a> {
+                    let fields: Box<[value::KeyValuePair<'a>]> = resource::CfgResource::changed_values(self,from).collect();
+                    let unset: Box<[&'static [u8]]> = resource::CfgResource::unset_fields(self,from).collect();
                     resource::ResourceMutation {
                         resource: #path,
                         operation: resource::ResourceMutationOperation::UpdateByKey(value::KeyValuePair {
                             key: #field_name,
                             value: value::RosValue::encode_ros(&from.0.#id_field_name),
                         }),
-                        fields: resource::CfgResource::changed_values(self,from).collect(),
+                        previous: resource::previous_values(&fields, &unset, from),
+                        fields,
+                        unset,
+                        provenance: None,
                         depends: <#id_struct_ident as resource::RosResource>::consumes_reference(self).filter(|(_,value)|!value.is_empty()).collect(),
                         provides: <#id_struct_ident as resource::RosResource>::provides_reference(self).filter(|(_,value)|!value.is_empty()).collect(),
                     }
@@ -558,6 +700,12 @@ impl Entity {
                 ) -> impl Iterator<Item = value::KeyValuePair<'a>> {
                     self.data.changed_values(&before.data)
                 }
+                fn unset_fields(&self, before: &Self) -> impl Iterator<Item = &'static [u8]> {
+                    self.data.unset_fields(&before.data)
+                }
+                fn field_values(&self) -> impl Iterator<Item = value::KeyValuePair<'_>> {
+                    self.data.field_values()
+                }
             }
         }
     }
@@ -565,6 +713,8 @@ impl Entity {
         let id_struct_ident = self.id_struct_type(id_field);
         let struct_ident = self.struct_ident_cfg();
         let changed_values_array = self.modifiable_field_updaters(&Some(parse_quote!(data)));
+        let unset_fields_fn =
+            self.unset_fields_fn(&Some(parse_quote!(data)), id_struct_ident.clone());
 
         parse_quote! {
             impl resource::SetResource<#id_struct_ident> for #struct_ident {
@@ -575,6 +725,7 @@ impl Entity {
                 ) -> impl Iterator<Item = value::KeyValuePair<'a>> {
                     #changed_values_array.into_iter().flatten()
                 }
+                #unset_fields_fn
             }
         }
     }
@@ -586,13 +737,18 @@ impl Entity {
         parse_quote! {
             impl resource::Updatable<#id_struct_ident> for #id_struct_ident {
                 fn calculate_update<'a>(&'a self, from: &'a #id_struct_ident) -> resource::ResourceMutation<'a> {
+                    let fields: Box<[value::KeyValuePair<'a>]> = resource::CfgResource::changed_values(self,from).collect();
+                    let unset: Box<[&'static [u8]]> = resource::CfgResource::unset_fields(self,from).collect();
                     resource::ResourceMutation {
                         resource: #path,
                         operation: resource::ResourceMutationOperation::UpdateByKey(value::KeyValuePair {
                             key: #key,
                             value: value::RosValue::encode_ros(&self.#key_name),
                         }),
-                        fields: resource::CfgResource::changed_values(self,from).collect(),
+                        previous: resource::previous_values(&fields, &unset, from),
+                        fields,
+                        unset,
+                        provenance: None,
                         depends: <#id_struct_ident as resource::RosResource>::consumes_reference(self).filter(|(_,value)|!value.is_empty()).collect(),
                         provides: <#id_struct_ident as resource::RosResource>::provides_reference(self).filter(|(_,value)|!value.is_empty()).collect(),
                     }
@@ -609,13 +765,18 @@ impl Entity {
         parse_quote! {
             impl resource::Updatable<#id_struct_ident> for #cfg_ident {
                 fn calculate_update<'a>(&'a self, from: &'a #id_struct_ident) -> resource::ResourceMutation<'a> {
+                    let fields: Box<[value::KeyValuePair<'a>]> = resource::SetResource::changed_values(self,from).collect();
+                    let unset: Box<[&'static [u8]]> = resource::SetResource::unset_fields(self,from).collect();
                     resource::ResourceMutation {
                         resource: #path,
                         operation: resource::ResourceMutationOperation::UpdateByKey(value::KeyValuePair {
                             key: #key,
                             value: value::RosValue::encode_ros(&from.#key_name),
                         }),
-                        fields: resource::SetResource::changed_values(self,from).collect(),
+                        previous: resource::previous_values(&fields, &unset, from),
+                        fields,
+                        unset,
+                        provenance: None,
                         depends: <#cfg_ident as resource::RosResource>::consumes_reference(self).filter(|(_,value)|!value.is_empty()).collect(),
                         provides: <#cfg_ident as resource::RosResource>::provides_reference(self).filter(|(_,value)|!value.is_empty()).collect(),
                     }
@@ -654,35 +815,61 @@ impl Entity {
         }
     }
 
-    fn dynamic_filter(&self) -> Option<Stmt> {
-        let has_dynamic = self.fields.iter().any(|f| {
-            f.name.as_ref() == "dynamic"
-                && f.field_type.as_deref() == Some("bool")
-                && f.is_read_only
-        });
-        let has_builtin = self.fields.iter().any(|f| {
-            f.name.as_ref() == "builtin"
+    /// Read only flags marking entries created by the device itself, which must not be
+    /// removed or modified.
+    fn unmanaged_fields(&self) -> impl Iterator<Item = &Field> {
+        self.fields.iter().filter(|f| {
+            matches!(f.name.as_ref(), "dynamic" | "builtin" | "default")
                 && f.field_type.as_deref() == Some("bool")
                 && f.is_read_only
-        });
+        })
+    }
 
-        match (has_dynamic, has_builtin) {
-            (true, true) => Some(parse_quote! {
-                fn filter(cmd: mikrotik_api::prelude::CommandBuilder) -> mikrotik_api::prelude::CommandBuilder {
-                    cmd.query_equal(b"dynamic",b"false").query_equal(b"builtin",b"false").query_operations(Some(mikrotik_api::prelude::QueryOperator::And).into_iter())
-                }
-            }),
-            (true, false) => Some(parse_quote! {
-                fn filter(cmd: mikrotik_api::prelude::CommandBuilder) -> mikrotik_api::prelude::CommandBuilder {
-                    cmd.query_equal(b"dynamic",b"false").query_not_present(b"dynamic").query_operations(Some(mikrotik_api::prelude::QueryOperator::Or).into_iter())
-                }
-            }),
-            (false, true) => Some(parse_quote! {
-                fn filter(cmd: mikrotik_api::prelude::CommandBuilder) -> mikrotik_api::prelude::CommandBuilder {
-                    cmd.query_equal(b"builtin",b"false")
+    fn dynamic_filter(&self) -> Option<Stmt> {
+        let unmanaged_fields = self.unmanaged_fields().collect::<Vec<_>>();
+        if unmanaged_fields.is_empty() {
+            return None;
+        }
+        // entries omitting a flag are user managed as well, see user_managed_fn
+        let mut query: Expr = parse_quote!(cmd);
+        for field in &unmanaged_fields {
+            let attribute_name = field.attribute_name();
+            query = parse_quote!(#query.query_equal(#attribute_name,b"false").query_not_present(#attribute_name).query_operations(Some(mikrotik_api::prelude::QueryOperator::Or).into_iter()));
+        }
+        if unmanaged_fields.len() > 1 {
+            let operators: Punctuated<Path, Comma> = (1..unmanaged_fields.len())
+                .map(|_| -> Path { parse_quote!(mikrotik_api::prelude::QueryOperator::And) })
+                .collect();
+            query = parse_quote!(#query.query_operations([#operators].into_iter()));
+        }
+        Some(parse_quote! {
+            fn filter(cmd: mikrotik_api::prelude::CommandBuilder) -> mikrotik_api::prelude::CommandBuilder {
+                #query
+            }
+        })
+    }
+
+    fn user_managed_fn(&self) -> Option<ImplItemFn> {
+        let checks: Punctuated<Expr, Token![&&]> = self
+            .unmanaged_fields()
+            .map(|field| {
+                let field_name = field.generate_field_name();
+                let check: Expr = if field.is_optional {
+                    parse_quote!(self.1.#field_name != Some(true))
+                } else {
+                    parse_quote!(!self.1.#field_name)
+                };
+                check
+            })
+            .collect();
+        if checks.is_empty() {
+            None
+        } else {
+            Some(parse_quote! {
+                fn is_user_managed(&self) -> bool {
+                    #checks
                 }
-            }),
-            (false, false) => None,
+            })
         }
     }
 
@@ -762,11 +949,11 @@ impl Entity {
         ident2type(self.id_struct_ident(id_field))
     }
 
-    fn id_struct_ident(&self, id_field: &Field) -> Ident {
+    pub(crate) fn id_struct_ident(&self, id_field: &Field) -> Ident {
         let struct_name = self.struct_name();
         crate::name2ident(&format!("{struct_name}By_{}", id_field.name))
     }
-    fn id_struct_ident_field(&self, id_field: &Field) -> Ident {
+    pub(crate) fn id_struct_ident_field(&self, id_field: &Field) -> Ident {
         let struct_name = self.struct_name();
         crate::name2field_ident(&format!("{struct_name}By_{}", id_field.name))
     }
@@ -838,6 +1025,9 @@ impl Entity {
                         resource: <#struct_ident_cfg as resource::RosResource>::path(),
                         operation: resource::ResourceMutationOperation::Add,
                         fields: #create_values_array.into_iter().filter(|value::KeyValuePair{key:_,value}|!value.is_empty()).collect(),
+                        previous: Box::new([]),
+                        unset: Box::new([]),
+                        provenance: None,
                         depends: <#struct_ident_cfg as resource::RosResource>::consumes_reference(self).filter(|(_,value)|!value.is_empty()).collect(),
                         provides: <#struct_ident_cfg as resource::RosResource>::provides_reference(self).filter(|(_,value)|!value.is_empty()).collect(),
                     }
@@ -880,10 +1070,15 @@ impl Entity {
         parse_quote! {
             impl resource::Updatable<#struct_ident_cfg> for #struct_ident_cfg {
                 fn calculate_update<'a>(&'a self, from: &'a Self) -> resource::ResourceMutation<'a> {
+                    let fields: Box<[value::KeyValuePair<'a>]> = resource::CfgResource::changed_values(self,from).collect();
+                    let unset: Box<[&'static [u8]]> = resource::CfgResource::unset_fields(self,from).collect();
                     resource::ResourceMutation {
                         resource: <#struct_ident_cfg as resource::RosResource>::path(),
                         operation: resource::ResourceMutationOperation::UpdateSingle,
-                        fields: resource::CfgResource::changed_values(self,from).collect(),
+                        previous: resource::previous_values(&fields, &unset, from),
+                        fields,
+                        unset,
+                        provenance: None,
                         depends: <#struct_ident_cfg as resource::RosResource>::consumes_reference(self).filter(|(_,value)|!value.is_empty()).collect(),
                         provides: <#struct_ident_cfg as resource::RosResource>::provides_reference(self).filter(|(_,value)|!value.is_empty()).collect(),
                     }
@@ -902,6 +1097,8 @@ impl Entity {
     fn create_cfg_resource(&self) -> Item {
         let struct_ident_cfg = self.struct_type_cfg();
         let changed_values_array = self.modifiable_field_updaters(&None);
+        let values_array = self.modifiable_field_creators();
+        let unset_fields_fn = self.unset_fields_fn(&None, parse_quote!(Self));
         parse_quote! {
             impl resource::CfgResource for #struct_ident_cfg {
                 #[allow(clippy::needless_lifetimes)]
@@ -911,6 +1108,10 @@ impl Entity {
                 ) -> impl Iterator<Item = value::KeyValuePair<'a>> {
                     #changed_values_array.into_iter().flatten()
                 }
+                #unset_fields_fn
+                fn field_values(&self) -> impl Iterator<Item = value::KeyValuePair<'_>> {
+                    #values_array.into_iter()
+                }
             }
         }
     }
@@ -963,7 +1164,12 @@ impl Entity {
         let mut consumes_expr = None;
         for (name, incoming, field) in self.referencing_fields() {
             if let Some(field_access) = field_gen(field) {
-                let expr: Expr = if field.is_multiple || field.is_optional {
+                let expr: Expr = if field.is_negatable || field.is_negatable_each {
+                    let values = field.negatable_reference_values(field_access);
+                    parse_quote! {#values
+                    .map(value::RosValue::encode_ros)
+                    .map(|value| (ReferenceType::#name, value))}
+                } else if field.is_multiple || field.is_optional {
                     parse_quote! {(#field_access).iter()
                     .map(value::RosValue::encode_ros)
                     .map(|value| (ReferenceType::#name, value))}
@@ -997,6 +1203,59 @@ impl Entity {
         Literal::byte_string(self.path.join("/").as_bytes())
     }
 
+    /// Match arms on `(path, key)` returning the encoded `default=` value of a field
+    pub fn generate_default_value_arms(&self) -> impl Iterator<Item = Arm> + '_ {
+        let path = self.generate_path();
+        self.fields.iter().filter_map(move |field| {
+            let default = Literal::byte_string(field.default.as_ref()?.as_bytes());
+            let attribute_name = field.attribute_name();
+            let field_type = self.struct_field_type(field);
+            Some(parse_quote! {
+                (#path, #attribute_name) => <#field_type as value::RosValue>::parse_ros(#default)
+                    .ok()
+                    .map(|value| std::borrow::Cow::Owned(value::RosValue::encode_ros(&value).into_owned()))
+            })
+        })
+    }
+
+    /// Match arm on `path` returning the names of the writable key fields, if there are any
+    pub fn generate_key_fields_arm(&self) -> Option<Arm> {
+        let path = self.generate_path();
+        let key_fields: Vec<_> = self
+            .fields
+            .iter()
+            .filter(|field| field.is_key && !field.is_read_only)
+            .map(|field| field.attribute_name())
+            .collect();
+        if key_fields.is_empty() {
+            None
+        } else {
+            Some(parse_quote! {
+                #path => &[#(#key_fields),*]
+            })
+        }
+    }
+
+    pub fn generate_reference_fields_arm(&self) -> Option<Arm> {
+        let path = self.generate_path();
+        let reference_fields: Vec<_> = self
+            .referencing_fields()
+            .filter(|(_, incoming, field)| *incoming && !field.is_read_only)
+            .map(|(reference, _, field)| {
+                let name = field.attribute_name();
+                let entry: Expr = parse_quote!((#name, ReferenceType::#reference));
+                entry
+            })
+            .collect();
+        if reference_fields.is_empty() {
+            None
+        } else {
+            Some(parse_quote! {
+                #path => &[#(#reference_fields),*]
+            })
+        }
+    }
+
     fn generate_deserialize_for_cfg_struct(&self) -> Item {
         Self::generate_deserialize(
             self.struct_type_cfg(),
@@ -1080,7 +1339,9 @@ impl Entity {
 
                     for field in fields_of_type {
                         let field_name = field.generate_field_name();
-                        if field.is_multiple {
+                        if field.is_negatable || field.is_negatable_each {
+                            type_block.stmts.push(field.negatable_update_reference(&field_name));
+                        } else if field.is_multiple {
                             if field.is_optional {
                                 type_block.stmts.push(parse_quote! {
                                     if let Some(values)=self.#field_name.as_mut(){
@@ -1227,11 +1488,27 @@ impl Entity {
         changed_values_array
     }
 
+    fn unset_fields_fn(&self, sub_field: &Option<Ident>, before_type: Type) -> Option<ImplItemFn> {
+        let unset_values: Punctuated<Expr, Comma> = self
+            .modifiable_fields_iterator()
+            .filter_map(|field| field.unset_snippet(sub_field))
+            .collect();
+        if unset_values.is_empty() {
+            None
+        } else {
+            Some(parse_quote! {
+                fn unset_fields(&self, before: &#before_type) -> impl Iterator<Item = &'static [u8]> {
+                    [#unset_values].into_iter().flatten()
+                }
+            })
+        }
+    }
+
     fn modifiable_field_declarations(&self, type_builder: impl Fn(&Field) -> Type) -> FieldsNamed {
         self.create_struct_fields(self.modifiable_fields_iterator(), type_builder)
     }
 
-    fn modifiable_fields_iterator(&self) -> impl Iterator<Item = &Field> {
+    pub(crate) fn modifiable_fields_iterator(&self) -> impl Iterator<Item = &Field> {
         self.fields
             .iter()
             .filter(|f| !f.is_read_only && MonitorVariant::Exclusive != f.monitor)
@@ -1286,7 +1563,7 @@ impl Entity {
         field.generate_base_field_type(self.enum_field_type(field).map(|(ty, _)| ident2type(ty)))
     }
 
-    fn enum_field_type(&self, field: &Field) -> Option<(Ident, Box<[Box<str>]>)> {
+    pub(crate) fn enum_field_type(&self, field: &Field) -> Option<(Ident, Box<[Box<str>]>)> {
         if let Some(enum_values) = field.inline_enum.as_ref() {
             let struct_name = self.struct_name();
             let enum_name = format!("{struct_name}_{}", field.name);
@@ -1301,11 +1578,11 @@ impl Entity {
         ident2type(self.struct_ident_cfg())
     }
 
-    fn struct_ident_cfg(&self) -> Ident {
+    pub(crate) fn struct_ident_cfg(&self) -> Ident {
         let struct_name = self.struct_name();
         crate::name2ident(&format!("{struct_name}Cfg"))
     }
-    fn struct_ident_cfg_field(&self) -> Ident {
+    pub(crate) fn struct_ident_cfg_field(&self) -> Ident {
         let struct_name = self.struct_name();
         crate::name2field_ident(&format!("{struct_name}Cfg"))
     }
@@ -1746,7 +2023,7 @@ impl Entity {
             builder: self.id_struct_builder_ident(id_field),
             data: self.id_struct_type(id_field),
             can_update: true,
-            can_add: false,
+            can_add: self.can_add && !id_field.is_read_only,
             is_single: false,
         }
     }
@@ -1814,6 +2091,8 @@ pub struct Field {
     pub is_optional: bool,
     pub is_read_only: bool,
     pub is_multiple: bool,
+    /// `mu` field keeping the order of its values
+    pub is_list: bool,
     pub is_hex: bool,
     pub reference: Reference,
     pub has_none: bool,
@@ -1823,6 +2102,10 @@ pub struct Field {
     pub is_rxtx_pair: bool,
     pub is_stats_pair: bool,
     pub keep_if_none: bool,
+    /// whole value may be negated with `!`
+    pub is_negatable: bool,
+    /// every value of a `mu` field may be negated with `!`
+    pub is_negatable_each: bool,
     pub default: Option<Box<str>>,
     pub monitor: MonitorVariant,
 }
@@ -1854,7 +2137,9 @@ impl Field {
                             if let Some(name) = value.strip_prefix(">") {
                                 field.reference = Reference::RefereesTo(name.trim().into());
                             } else {
-                                field.reference = Reference::IsReference(value.into());
+                                field.reference = Reference::IsReference(
+                                    value.split(',').map(|name| name.trim().into()).collect(),
+                                );
                             }
                         }
                         "default" => field.default = Some(value.trim().into()),
@@ -1867,6 +2152,7 @@ impl Field {
                         "ro" => field.is_read_only = true,
                         "auto" => field.has_auto = true,
                         "mu" => field.is_multiple = true,
+                        "list" => field.is_list = true,
                         "range" => field.is_range_dash = true,
                         "range-dot" => field.is_range_dot = true,
                         "o" => field.is_optional = true,
@@ -1878,6 +2164,8 @@ impl Field {
                         "statspair" => field.is_stats_pair = true,
                         "disabled" => field.has_disabled = true,
                         "k" => field.keep_if_none = true,
+                        "neg" => field.is_negatable = true,
+                        "neg-each" => field.is_negatable_each = true,
                         "monitor" => field.monitor = MonitorVariant::Enabled,
                         "monitor-only" => field.monitor = MonitorVariant::Exclusive,
                         name => {
@@ -1888,6 +2176,12 @@ impl Field {
                     }
                 }
             }
+            if field.is_list && !field.is_multiple {
+                panic!("list needs mu: {definition}");
+            }
+            if field.is_list && field.reference != Reference::None {
+                panic!("list cannot be combined with ref: {definition}");
+            }
             Some(field)
         } else {
             Some(line.trim())
@@ -1910,6 +2204,9 @@ impl Field {
         if self.is_multiple {
             write!(writer, "mu; ")?;
         }
+        if self.is_list {
+            write!(writer, "list; ")?;
+        }
         if self.is_range_dash {
             write!(writer, "range; ")?;
         }
@@ -1943,13 +2240,19 @@ impl Field {
         if self.keep_if_none {
             write!(writer, "k; ")?;
         }
+        if self.is_negatable {
+            write!(writer, "neg; ")?;
+        }
+        if self.is_negatable_each {
+            write!(writer, "neg-each; ")?;
+        }
         if self.has_auto {
             write!(writer, "auto; ")?;
         }
         match &self.reference {
             Reference::None => {}
-            Reference::IsReference(target) => {
-                write!(writer, "ref={}; ", target)?;
+            Reference::IsReference(targets) => {
+                write!(writer, "ref={}; ", targets.join(","))?;
             }
             Reference::RefereesTo(target) => {
                 write!(writer, "ref=>{}; ", target)?;
@@ -1976,13 +2279,13 @@ impl Field {
     fn compare_and_set_snippet(&self, sub_field: &Option<Ident>) -> Expr {
         let field_name = self.generate_field_name();
         let attribute_name = self.attribute_name();
-        let keep_if_none = self.is_optional && self.keep_if_none;
         let field_ref: ExprField = if let Some(field) = sub_field {
             parse_quote!(before.#field.#field_name)
         } else {
             parse_quote!(before.#field_name)
         };
-        let cmp: Expr = if keep_if_none {
+        let cmp: Expr = if self.is_optional {
+            // returning to none is handled by unset, unless the field keeps its value
             parse_quote!(self.#field_name == #field_ref || self.#field_name.is_none())
         } else {
             parse_quote!(self.#field_name == #field_ref)
@@ -2000,6 +2303,88 @@ impl Field {
         compare_and_set_snippet
     }
 
+    fn unset_snippet(&self, sub_field: &Option<Ident>) -> Option<Expr> {
+        if !self.is_optional || self.keep_if_none {
+            return None;
+        }
+        let field_name = self.generate_field_name();
+        let attribute_name = self.attribute_name();
+        let field_ref: ExprField = if let Some(field) = sub_field {
+            parse_quote!(before.#field.#field_name)
+        } else {
+            parse_quote!(before.#field_name)
+        };
+        Some(parse_quote! {
+            if self.#field_name.is_none() && #field_ref.is_some() {
+                Some(&#attribute_name[..])
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Referenced values of a negatable field, without the negation
+    fn negatable_reference_values(&self, field_access: Expr) -> Expr {
+        let field_access = match field_access {
+            Expr::Reference(reference) => *reference.expr,
+            field_access => field_access,
+        };
+        let values: Expr = match (self.is_optional, self.is_negatable, self.is_multiple) {
+            (true, true, true) => parse_quote!((#field_access).iter().flat_map(|v| v.value.iter())),
+            (true, true, false) => parse_quote!((#field_access).iter().map(|v| &v.value)),
+            (true, false, _) => parse_quote!((#field_access).iter().flatten()),
+            (false, true, true) => parse_quote!((#field_access).value.iter()),
+            (false, true, false) => parse_quote!(Some(&(#field_access).value).into_iter()),
+            (false, false, _) => parse_quote!((#field_access).iter()),
+        };
+        if self.is_negatable_each {
+            parse_quote!(#values.map(|v| &v.value))
+        } else {
+            values
+        }
+    }
+
+    /// Replaces `old_value` by `new_value` in a negatable field, keeping the negation
+    fn negatable_update_reference(&self, field_name: &Ident) -> Stmt {
+        let inner: Expr = match (self.is_optional, self.is_negatable) {
+            (true, true) => parse_quote!(self.#field_name.as_mut().map(|v| &mut v.value)),
+            (true, false) => parse_quote!(self.#field_name.as_mut()),
+            (false, true) => parse_quote!(Some(&mut self.#field_name.value)),
+            (false, false) => parse_quote!(Some(&mut self.#field_name)),
+        };
+        if self.is_negatable_each {
+            parse_quote! {
+                if let Some(values) = #inner {
+                    let renamed = values
+                        .iter()
+                        .filter(|v| &v.value == old_value)
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    for mut v in renamed {
+                        values.remove(&v);
+                        v.value = new_value.clone();
+                        values.insert(v);
+                        modified = true;
+                    }
+                }
+            }
+        } else if self.is_multiple {
+            parse_quote! {
+                if let Some(values) = #inner && values.remove(old_value) {
+                    values.insert(new_value.clone());
+                    modified = true;
+                }
+            }
+        } else {
+            parse_quote! {
+                if let Some(value) = #inner && old_value == &*value {
+                    *value = new_value.clone();
+                    modified = true;
+                }
+            }
+        }
+    }
+
     pub fn generate_struct_field_type(&self, enum_field_type: Option<Type>) -> Type {
         let field_type = self.generate_base_field_type(enum_field_type);
         let field_type = if self.is_range_dash {
@@ -2012,11 +2397,23 @@ impl Field {
         } else {
             field_type
         };
-        let field_type = if self.is_multiple {
+        let field_type = if self.is_negatable_each {
+            parse_quote!(value::Negatable<#field_type>)
+        } else {
+            field_type
+        };
+        let field_type = if self.is_multiple && self.is_list {
+            parse_quote!(Vec<#field_type>)
+        } else if self.is_multiple {
             parse_quote!(std::collections::BTreeSet<#field_type>)
         } else {
             field_type
         };
+        let field_type = if self.is_negatable {
+            parse_quote!(value::Negatable<#field_type>)
+        } else {
+            field_type
+        };
         if self.is_optional {
             parse_quote!(Option<#field_type>)
         } else {
@@ -2133,7 +2530,8 @@ pub struct EnumDescriptions(pub HashMap<Box<str>, Box<[Box<str>]>>);
 pub enum Reference {
     #[default]
     None,
-    IsReference(Box<str>),
+    /// Provides the references of all given names, e.g. `ref=vrf,routing-table`
+    IsReference(Box<[Box<str>]>),
     RefereesTo(Box<str>),
 }
 
@@ -2157,4 +2555,27 @@ mod test {
         let collected_entities = Entity::parse_lines(lines);
         println!("{:#?}", collected_entities);
     }
+
+    #[test]
+    fn test_list_field() {
+        let field = Field::parse_field_line("servers: mu; list; IpAddr").unwrap();
+        assert!(field.is_list);
+        let field_type = field.generate_struct_field_type(None);
+        assert_eq!(field_type, parse_quote!(Vec<IpAddr>));
+        let mut line = String::new();
+        field.write_field_line(&mut line).unwrap();
+        assert_eq!(Field::parse_field_line(&line), Some(field));
+    }
+
+    #[test]
+    #[should_panic(expected = "list needs mu")]
+    fn test_list_without_mu() {
+        Field::parse_field_line("servers: list; IpAddr");
+    }
+
+    #[test]
+    #[should_panic(expected = "list cannot be combined with ref")]
+    fn test_list_with_ref() {
+        Field::parse_field_line("interfaces: mu; list; ref=>interface");
+    }
 }
@@ -0,0 +1,209 @@
+use crate::model::{Entity, EnumDescriptions, Field, Reference};
+use serde_json::{json, Map, Value};
+
+const INTEGER_TYPES: [&str; 8] = ["u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64"];
+
+/// JSON Schema of the configurable fields of `entity`, as read by the `serde` implementation of
+/// the generated `*Cfg` type.
+pub fn entity_schema(entity: &Entity, enums: &EnumDescriptions) -> Value {
+    object_schema(entity, entity.modifiable_fields_iterator(), enums)
+}
+
+/// JSON Schema of `model::Data`, every entity type is described in `$defs`.
+pub fn data_schema() -> Value {
+    let enums = crate::known_enums();
+    let mut definitions = Map::new();
+    let mut properties = Map::new();
+    for entity in crate::known_entities() {
+        if entity.modifiable_fields_iterator().next().is_none() {
+            continue;
+        }
+        let cfg_name = entity.struct_ident_cfg().to_string();
+        let cfg_ref = json!({ "$ref": format!("#/$defs/{cfg_name}") });
+        definitions.insert(cfg_name, entity_schema(&entity, &enums));
+        let cfg_field = entity.struct_ident_cfg_field().to_string();
+        if entity.is_single {
+            properties.insert(cfg_field, cfg_ref);
+            continue;
+        }
+        if entity.can_add {
+            properties.insert(cfg_field, array_schema(cfg_ref.clone()));
+        }
+        for key_field in entity.fields.iter().filter(|f| f.is_key) {
+            let item = if key_field.is_read_only {
+                let name = entity.id_struct_ident(key_field).to_string();
+                let schema = object_schema(
+                    &entity,
+                    Some(key_field)
+                        .into_iter()
+                        .chain(entity.modifiable_fields_iterator()),
+                    &enums,
+                );
+                definitions.insert(name.clone(), schema);
+                json!({ "$ref": format!("#/$defs/{name}") })
+            } else {
+                cfg_ref.clone()
+            };
+            properties.insert(
+                entity.id_struct_ident_field(key_field).to_string(),
+                array_schema(item),
+            );
+        }
+    }
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Data",
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+        "$defs": definitions,
+    })
+}
+
+fn array_schema(item: Value) -> Value {
+    json!({ "type": "array", "items": item })
+}
+
+fn object_schema<'a>(
+    entity: &Entity,
+    fields: impl Iterator<Item = &'a Field>,
+    enums: &EnumDescriptions,
+) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for field in fields {
+        if !field.is_optional && !field.is_multiple {
+            required.push(Value::from(field.name.as_ref()));
+        }
+        properties.insert(field.name.to_string(), field_schema(entity, field, enums));
+    }
+    json!({
+        "title": entity.path.join("/"),
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+fn field_schema(entity: &Entity, field: &Field, enums: &EnumDescriptions) -> Value {
+    let textual = field.is_multiple
+        || field.is_range_dot
+        || field.is_range_dash
+        || field.is_rxtx_pair
+        || field.is_stats_pair
+        || field.is_hex
+        || field.is_negatable
+        || field.is_negatable_each;
+    let enum_values = entity
+        .enum_field_type(field)
+        .map(|(_, values)| values)
+        .or_else(|| enums.0.get(field.field_type.as_deref()?).cloned());
+    let special_values = [
+        (field.has_auto, "auto"),
+        (field.has_unlimited, "unlimited"),
+        (field.has_none, "none"),
+        (field.has_disabled, "disabled"),
+        (field.has_never, "never"),
+    ]
+    .into_iter()
+    .filter(|(enabled, _)| *enabled)
+    .map(|(_, value)| Value::from(value))
+    .collect::<Vec<_>>();
+
+    let mut schema = match (&enum_values, field.field_type.as_deref()) {
+        _ if textual => json!({ "type": "string" }),
+        (Some(values), _) => {
+            let mut values = values
+                .iter()
+                .map(|value| Value::from(value.as_ref()))
+                .collect::<Vec<_>>();
+            values.extend(special_values.iter().cloned());
+            json!({ "enum": values })
+        }
+        (None, Some("bool")) => with_special_values(
+            json!({ "anyOf": [{ "type": "boolean" }, { "enum": ["yes", "no", "true", "false"] }] }),
+            &special_values,
+        ),
+        (None, Some(field_type)) if INTEGER_TYPES.contains(&field_type) => with_special_values(
+            json!({ "anyOf": [{ "type": "integer" }, { "type": "string", "pattern": "^-?[0-9]+$" }] }),
+            &special_values,
+        ),
+        _ => json!({ "type": "string" }),
+    };
+
+    let mut description = Vec::new();
+    if field.is_read_only {
+        description.push("read-only".to_string());
+    }
+    if field.is_multiple {
+        description.push("comma separated list".to_string());
+    }
+    if field.is_negatable || field.is_negatable_each {
+        description.push("negated with a leading !".to_string());
+    }
+    match &field.reference {
+        Reference::None => {}
+        Reference::IsReference(names) => {
+            description.push(format!("references {}", names.join(", ")));
+        }
+        Reference::RefereesTo(name) => {
+            description.push(format!("references {name}"));
+        }
+    }
+    if let Some(default) = &field.default {
+        schema["default"] = Value::from(default.as_ref());
+    }
+    if !description.is_empty() {
+        schema["description"] = Value::from(description.join(", "));
+    }
+    schema
+}
+
+fn with_special_values(schema: Value, special_values: &[Value]) -> Value {
+    if special_values.is_empty() {
+        schema
+    } else {
+        json!({ "anyOf": [schema, { "enum": special_values }] })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_vlan_schema() {
+        let schema = data_schema();
+        let vlan = &schema["$defs"]["InterfaceVlanCfg"];
+        assert_eq!(vlan["title"], "interface/vlan");
+        assert_eq!(
+            vlan["properties"]["loop-protect"]["enum"],
+            json!(["default", "off", "on"])
+        );
+        assert_eq!(
+            vlan["properties"]["interface"]["description"],
+            "references interface"
+        );
+        assert!(vlan["required"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("vlan-id")));
+        assert!(!vlan["required"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("comment")));
+        assert_eq!(
+            schema["properties"]["interface_vlan_by_name"]["items"]["$ref"],
+            "#/$defs/InterfaceVlanCfg"
+        );
+        assert_eq!(
+            schema["properties"]["interface_ethernet_by_default_name"]["items"]["$ref"],
+            "#/$defs/InterfaceEthernetByDefaultName"
+        );
+        assert_eq!(
+            schema["properties"]["system_identity_cfg"]["$ref"],
+            "#/$defs/SystemIdentityCfg"
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use crate::resource::{self, ResourceMutation, ResourceMutationError, ResourceMutationOperation};
+use crate::value::KeyValuePair;
+use encoding_rs::mem::decode_latin1;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConvergenceError {
+    #[error("Cannot refetch device state: {0}")]
+    Fetch(#[from] resource::Error),
+    #[error("Cannot compare device state: {0}")]
+    Mutation(#[from] Box<ResourceMutationError>),
+}
+
+/// Differences left between the target and the device state after applying the mutations.
+///
+/// Every remaining difference hints to a field the device did not accept as sent, e.g.
+/// because it normalizes the value or ignores it silently, so the `ros_model` description of
+/// that field is probably wrong.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ConvergenceReport {
+    pub resources: BTreeMap<&'static [u8], Vec<Divergence>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    /// entry expected but not found on the device
+    Missing {
+        fields: Box<[KeyValuePair<'static>]>,
+    },
+    /// entry found on the device but not expected
+    Unexpected { key: KeyValuePair<'static> },
+    /// fields still differing, `key` is `None` for single resources
+    Changed {
+        key: Option<KeyValuePair<'static>>,
+        fields: Box<[FieldDivergence]>,
+    },
+    /// entry not at the expected position of an ordered list
+    Misplaced { key: KeyValuePair<'static> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDivergence {
+    pub key: &'static [u8],
+    pub expected: Cow<'static, [u8]>,
+    /// value reported by the device, empty if unknown
+    pub actual: Cow<'static, [u8]>,
+}
+
+impl ConvergenceReport {
+    /// Builds the report from the mutations calculated against the refetched device state.
+    pub fn from_mutations<'m, 'a: 'm>(
+        mutations: impl IntoIterator<Item = &'m ResourceMutation<'a>>,
+    ) -> Self {
+        let mut resources = BTreeMap::<_, Vec<_>>::new();
+        for mutation in mutations {
+            let divergence = match &mutation.operation {
+                ResourceMutationOperation::Add => Divergence::Missing {
+                    fields: owned_fields(&mutation.fields),
+                },
+                ResourceMutationOperation::RemoveByKey(key) => Divergence::Unexpected {
+                    key: key.clone().into_owned(),
+                },
+                ResourceMutationOperation::UpdateSingle => Divergence::Changed {
+                    key: None,
+                    fields: field_divergences(mutation),
+                },
+                ResourceMutationOperation::UpdateByKey(key) => Divergence::Changed {
+                    key: Some(key.clone().into_owned()),
+                    fields: field_divergences(mutation),
+                },
+                ResourceMutationOperation::Move { key, .. } => Divergence::Misplaced {
+                    key: key.clone().into_owned(),
+                },
+            };
+            if let Divergence::Changed { fields, .. } = &divergence
+                && fields.is_empty()
+            {
+                continue;
+            }
+            resources
+                .entry(mutation.resource)
+                .or_default()
+                .push(divergence);
+        }
+        Self { resources }
+    }
+    pub fn is_converged(&self) -> bool {
+        self.resources.is_empty()
+    }
+}
+
+fn owned_fields(fields: &[KeyValuePair]) -> Box<[KeyValuePair<'static>]> {
+    fields
+        .iter()
+        .cloned()
+        .map(KeyValuePair::into_owned)
+        .collect()
+}
+
+fn field_divergences(mutation: &ResourceMutation) -> Box<[FieldDivergence]> {
+    let unset = mutation.unset.iter().map(|&key| (key, &b""[..]));
+    mutation
+        .fields
+        .iter()
+        .map(|field| (field.key, field.value.as_ref()))
+        .chain(unset)
+        .map(|(key, expected)| FieldDivergence {
+            key,
+            expected: Cow::Owned(expected.to_vec()),
+            actual: mutation
+                .previous
+                .iter()
+                .find(|previous| previous.key == key)
+                .map(|previous| Cow::Owned(previous.value.to_vec()))
+                .unwrap_or(Cow::Borrowed(b"")),
+        })
+        .collect()
+}
+
+impl Display for ConvergenceReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (path, divergences) in &self.resources {
+            let path = decode_latin1(path);
+            for divergence in divergences {
+                match divergence {
+                    Divergence::Missing { .. } => writeln!(f, "{path}: missing entry")?,
+                    Divergence::Unexpected { key } => writeln!(f, "{path} [{key:?}]: unexpected")?,
+                    Divergence::Changed { key, fields } => {
+                        for field in fields {
+                            write!(f, "{path}")?;
+                            if let Some(key) = key {
+                                write!(f, " [{key:?}]")?;
+                            }
+                            writeln!(
+                                f,
+                                " {}: expected {}, got {}",
+                                decode_latin1(field.key),
+                                decode_latin1(&field.expected),
+                                decode_latin1(&field.actual)
+                            )?;
+                        }
+                    }
+                    Divergence::Misplaced { key } => writeln!(f, "{path} [{key:?}]: misplaced")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::SystemIdentityCfg;
+    use crate::resource::Updatable;
+
+    #[test]
+    fn test_report_changed_field() {
+        let device_state = SystemIdentityCfg {
+            name: b"Router".into(),
+        };
+        let target = SystemIdentityCfg {
+            name: b"router".into(),
+        };
+        let unchanged = target.calculate_update(&target);
+        let changed = target.calculate_update(&device_state);
+        let report = ConvergenceReport::from_mutations([&unchanged, &changed]);
+        assert!(!report.is_converged());
+        assert_eq!(
+            report.resources.get(&b"system/identity"[..]),
+            Some(&vec![Divergence::Changed {
+                key: None,
+                fields: Box::new([FieldDivergence {
+                    key: b"name",
+                    expected: Cow::Borrowed(b"router"),
+                    actual: Cow::Borrowed(b"Router"),
+                }]),
+            }])
+        );
+        assert!(ConvergenceReport::from_mutations([&unchanged]).is_converged());
+    }
+}
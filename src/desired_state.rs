@@ -0,0 +1,118 @@
+use serde::de::{self, DeserializeOwned};
+use std::{
+    collections::{BTreeMap, btree_map::Entry},
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Error while loading a desired-state file, the message starts with the path of the file and
+/// parse errors contain line and column of the offending entry.
+#[derive(Debug, Error)]
+pub enum DesiredStateError {
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: serde_yaml::Error,
+    },
+}
+
+/// Loads a desired state from a YAML file, typically into a `*Target` struct generated by
+/// `mikrotik_model!`.
+///
+/// Every field of the target is a top level key of the file, single resources are written as
+/// a map of RouterOS field names, all others as a list of such maps including the key fields.
+/// Unknown keys are rejected.
+pub fn load_yaml<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, DesiredStateError> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|source| DesiredStateError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    serde_yaml::from_reader(file).map_err(|source| DesiredStateError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Collects the entries of a list in a desired-state file into the map of a `*Target` struct,
+/// rejecting a key given twice.
+pub fn collect_unique<K: Ord, V, E: de::Error>(
+    field: &str,
+    entries: impl IntoIterator<Item = (K, V)>,
+) -> Result<BTreeMap<K, V>, E> {
+    let mut map = BTreeMap::new();
+    for (idx, (key, value)) in entries.into_iter().enumerate() {
+        match map.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+            Entry::Occupied(_) => {
+                return Err(E::custom(format!(
+                    "{field}[{idx}]: duplicate entry with the same key"
+                )));
+            }
+        }
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{InterfaceVlanByName, InterfaceVlanCfg, SystemIdentityCfg};
+    use crate::{MikrotikDevice, ascii, mikrotik_model};
+    use std::io::Write;
+
+    mikrotik_model!(
+        name = DesiredStateData,
+        fields(vlan(by_key(path = "interface/vlan", key = name)))
+    );
+
+    #[test]
+    fn test_unknown_key_location() {
+        let path = std::env::temp_dir().join(format!("desired-state-{}.yaml", std::process::id()));
+        File::create(&path)
+            .unwrap()
+            .write_all(b"- name: router\n- name: switch\n  nmae: switch\n")
+            .unwrap();
+        let result = load_yaml::<Vec<SystemIdentityCfg>>(&path);
+        std::fs::remove_file(&path).unwrap();
+        let message = result.unwrap_err().to_string();
+        assert!(
+            message.starts_with(&path.display().to_string()),
+            "{message}"
+        );
+        assert!(message.contains("nmae"), "{message}");
+        assert!(message.contains("line 2"), "{message}");
+    }
+
+    #[test]
+    fn test_duplicate_key() {
+        let vlan = InterfaceVlanByName(InterfaceVlanCfg {
+            name: b"vlan10".into(),
+            interface: b"ether1".into(),
+            vlan_id: 10,
+            ..InterfaceVlanCfg::default()
+        });
+        let yaml = format!(
+            "vlan:\n{}",
+            serde_yaml::to_string(&[vlan.clone(), vlan]).unwrap()
+        );
+        let message = serde_yaml::from_str::<DesiredStateDataTarget>(&yaml)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            message.contains("vlan[1]: duplicate entry with the same key"),
+            "{message}"
+        );
+    }
+}
@@ -0,0 +1,464 @@
+use crate::model::{Data, ResourceType};
+use crate::resource::{AppendFieldResult, ResourceAccessError};
+use crate::value::KeyValuePair;
+use thiserror::Error;
+
+/// Commands which end the path of a command line like `/interface bridge add name=bridge`
+const COMMANDS: &[&[u8]] = &[
+    b"add", b"set", b"remove", b"unset", b"move", b"enable", b"disable", b"edit", b"reset",
+];
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("line {line}: {message}")]
+    Syntax { line: usize, message: &'static str },
+    #[error("line {line}: {error}")]
+    Resource {
+        line: usize,
+        error: ResourceAccessError,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportWarning {
+    /// no modelled resource stored in [`Data`] at this path, the command was skipped
+    UnknownPath { line: usize, path: Box<[u8]> },
+    /// only `add` and `set` are applied, other commands are skipped
+    UnsupportedCommand { line: usize, command: Box<[u8]> },
+    /// field not part of the model, its value was ignored
+    UnknownField { line: usize, field: Box<[u8]> },
+    /// `set` on an entry not added before, e.g. a default entry left out by `/export`, the
+    /// command was skipped
+    UnknownEntry { line: usize, path: Box<[u8]> },
+}
+
+/// Single command of an export script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportCommand {
+    /// line the command starts at, counting from 1
+    pub line: usize,
+    /// path without leading slash, e.g. `interface/bridge`
+    pub path: Box<[u8]>,
+    pub command: Box<[u8]>,
+    /// conditions of a `[ find ... ]` selector, or the name given as plain argument
+    pub find: Box<[ExportField]>,
+    pub fields: Box<[ExportField]>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportField {
+    pub key: Box<[u8]>,
+    /// value with quotes and escapes removed
+    pub value: Box<[u8]>,
+}
+
+/// Parses a script written by `/export` into its commands.
+///
+/// Handles path headers, `[ find ... ]` selectors, quoted strings with `\` escapes, line
+/// continuations and comments.
+pub fn parse_export(script: &[u8]) -> Result<Vec<ExportCommand>, ExportError> {
+    let mut commands = Vec::new();
+    let mut path: Box<[u8]> = Box::new([]);
+    for (line, content) in logical_lines(script) {
+        let syntax_error = |message| ExportError::Syntax { line, message };
+        let mut tokens = tokenize(&content)
+            .map_err(syntax_error)?
+            .into_iter()
+            .peekable();
+        if let Some(first) = tokens.next_if(|token| token.starts_with(b"/")) {
+            let mut segments = vec![first[1..].to_vec()];
+            while let Some(segment) = tokens.next_if(|token| {
+                !token.contains(&b'=')
+                    && !token.starts_with(b"[")
+                    && !COMMANDS.contains(&token.as_slice())
+            }) {
+                segments.push(segment);
+            }
+            path = segments
+                .iter()
+                .flat_map(|segment| segment.split(|&ch| ch == b'/'))
+                .filter(|segment| !segment.is_empty())
+                .collect::<Vec<_>>()
+                .join(&b'/')
+                .into_boxed_slice();
+        }
+        let Some(command) = tokens.next() else {
+            continue;
+        };
+        let mut find = Vec::new();
+        let mut fields = Vec::new();
+        for token in tokens {
+            if let Some(selector) = token.strip_prefix(b"[") {
+                find.extend(parse_selector(selector).map_err(syntax_error)?);
+            } else if !token.contains(&b'=') && find.is_empty() && fields.is_empty() {
+                find.push(ExportField {
+                    key: Box::from(&b"name"[..]),
+                    value: unquote(&token).map_err(syntax_error)?.into_boxed_slice(),
+                });
+            } else {
+                fields.push(parse_field(&token).map_err(syntax_error)?);
+            }
+        }
+        commands.push(ExportCommand {
+            line,
+            path: path.clone(),
+            command: command.into_boxed_slice(),
+            find: find.into_boxed_slice(),
+            fields: fields.into_boxed_slice(),
+        });
+    }
+    Ok(commands)
+}
+
+/// Applies the `add` and `set` commands of an export script to `data`.
+///
+/// `/export` omits fields left at their default value, entries missing a required field are
+/// rejected, so the script should be written by `/export verbose`.
+pub fn load_export(data: &mut Data, script: &[u8]) -> Result<Vec<ExportWarning>, ExportError> {
+    let mut warnings = Vec::new();
+    for command in parse_export(script)? {
+        apply_command(data, &command, &mut warnings)?;
+    }
+    Ok(warnings)
+}
+
+fn apply_command(
+    data: &mut Data,
+    command: &ExportCommand,
+    warnings: &mut Vec<ExportWarning>,
+) -> Result<(), ExportError> {
+    let line = command.line;
+    let is_set = match command.command.as_ref() {
+        b"add" => false,
+        b"set" => true,
+        _ => {
+            warnings.push(ExportWarning::UnsupportedCommand {
+                line,
+                command: command.command.clone(),
+            });
+            return Ok(());
+        }
+    };
+    let key = command
+        .find
+        .first()
+        .map(|field| (field.key.as_ref(), field.value.as_ref()));
+    let mut stored = false;
+    let mut unknown_entry = false;
+    let mut missing_field = None;
+    // fields unknown to every resource type the command was stored as
+    let mut ignored_fields: Option<Vec<Box<[u8]>>> = None;
+    for resource_type in
+        enum_iterator::all::<ResourceType>().filter(|t| t.path() == command.path.as_ref())
+    {
+        let (position, previous) = if is_set {
+            let Some((position, values)) = data.entry_values(resource_type, key) else {
+                unknown_entry = true;
+                continue;
+            };
+            let values = values
+                .into_iter()
+                .map(KeyValuePair::into_owned)
+                .collect::<Vec<_>>();
+            (position, values)
+        } else {
+            (None, Vec::new())
+        };
+        let mut builder = resource_type.create_builder();
+        for kv in &previous {
+            builder.append_field(kv.key, Some(kv.value.as_ref()));
+        }
+        let mut unknown_fields = Vec::new();
+        for field in command.find.iter().chain(command.fields.iter()) {
+            match builder.append_field(&field.key, Some(&field.value)) {
+                AppendFieldResult::Appended => {}
+                AppendFieldResult::InvalidValue(field_name) => {
+                    return Err(ExportError::Resource {
+                        line,
+                        error: ResourceAccessError::InvalidValueError {
+                            field_name,
+                            value: field.value.clone(),
+                        },
+                    });
+                }
+                AppendFieldResult::UnknownField => unknown_fields.push(field.key.clone()),
+            }
+        }
+        match builder.build() {
+            Ok(resource) => {
+                if data.insert(resource, position) {
+                    stored = true;
+                    ignored_fields = Some(match ignored_fields {
+                        None => unknown_fields,
+                        Some(ignored) => ignored
+                            .into_iter()
+                            .filter(|field| unknown_fields.contains(field))
+                            .collect(),
+                    });
+                }
+            }
+            Err(field_name) => {
+                missing_field.get_or_insert(field_name);
+            }
+        }
+    }
+    match (stored, missing_field) {
+        (false, None) if unknown_entry => warnings.push(ExportWarning::UnknownEntry {
+            line,
+            path: command.path.clone(),
+        }),
+        (true, _) => warnings.extend(
+            ignored_fields
+                .into_iter()
+                .flatten()
+                .map(|field| ExportWarning::UnknownField { line, field }),
+        ),
+        (false, Some(field_name)) => {
+            return Err(ExportError::Resource {
+                line,
+                error: ResourceAccessError::UndefinedFieldError { field_name },
+            });
+        }
+        (false, None) => warnings.push(ExportWarning::UnknownPath {
+            line,
+            path: command.path.clone(),
+        }),
+    }
+    Ok(())
+}
+
+/// Joins continued lines and drops comments, returns every line with its starting line number.
+fn logical_lines(script: &[u8]) -> Vec<(usize, Vec<u8>)> {
+    let mut lines = Vec::new();
+    let mut current = Vec::new();
+    let mut line = 1;
+    let mut start = 1;
+    let mut in_quotes = false;
+    let mut idx = 0;
+    while idx < script.len() {
+        let ch = script[idx];
+        idx += 1;
+        match ch {
+            b'\\' if script[idx..].starts_with(b"\n") || script[idx..].starts_with(b"\r\n") => {
+                idx += if script[idx] == b'\r' { 2 } else { 1 };
+                line += 1;
+                while idx < script.len() && matches!(script[idx], b' ' | b'\t') {
+                    idx += 1;
+                }
+                if !in_quotes {
+                    current.push(b' ');
+                }
+            }
+            b'\\' => {
+                current.push(ch);
+                if let Some(&escaped) = script.get(idx) {
+                    current.push(escaped);
+                    idx += 1;
+                }
+            }
+            b'"' => {
+                in_quotes = !in_quotes;
+                current.push(ch);
+            }
+            b'\n' => {
+                push_line(&mut lines, start, &mut current);
+                line += 1;
+                start = line;
+                in_quotes = false;
+            }
+            b'\r' => {}
+            _ => current.push(ch),
+        }
+    }
+    push_line(&mut lines, start, &mut current);
+    lines
+}
+
+fn push_line(lines: &mut Vec<(usize, Vec<u8>)>, start: usize, current: &mut Vec<u8>) {
+    let content = std::mem::take(current);
+    let trimmed = content.trim_ascii();
+    if !trimmed.is_empty() && !trimmed.starts_with(b"#") {
+        lines.push((start, trimmed.to_vec()));
+    }
+}
+
+/// Splits at whitespace outside of quotes and brackets.
+fn tokenize(line: &[u8]) -> Result<Vec<Vec<u8>>, &'static str> {
+    let mut tokens = Vec::new();
+    let mut current = Vec::new();
+    let mut in_quotes = false;
+    let mut depth = 0usize;
+    let mut escaped = false;
+    for ch in line.iter().copied() {
+        if escaped {
+            escaped = false;
+            current.push(ch);
+            continue;
+        }
+        match ch {
+            b'\\' => escaped = true,
+            b'"' => in_quotes = !in_quotes,
+            b'[' if !in_quotes => depth += 1,
+            b']' if !in_quotes => depth = depth.checked_sub(1).ok_or("unbalanced ]")?,
+            b' ' | b'\t' if !in_quotes && depth == 0 => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        current.push(ch);
+    }
+    if in_quotes {
+        return Err("unterminated string");
+    }
+    if depth > 0 {
+        return Err("unterminated [");
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+/// Parses the content of `[ find where key=value ... ]` after the opening bracket.
+fn parse_selector(selector: &[u8]) -> Result<Vec<ExportField>, &'static str> {
+    let selector = selector.strip_suffix(b"]").ok_or("unterminated [")?;
+    let mut tokens = tokenize(selector)?.into_iter();
+    if tokens.next().as_deref() != Some(b"find") {
+        return Err("only [ find ... ] selectors are supported");
+    }
+    tokens
+        .filter(|token| token != b"where")
+        .map(|token| parse_field(&token))
+        .collect()
+}
+
+fn parse_field(token: &[u8]) -> Result<ExportField, &'static str> {
+    let (key, value) = match token.iter().position(|&ch| ch == b'=') {
+        Some(idx) => (&token[..idx], unquote(&token[idx + 1..])?),
+        None => (token, Vec::new()),
+    };
+    Ok(ExportField {
+        key: Box::from(key),
+        value: value.into_boxed_slice(),
+    })
+}
+
+/// Removes quotes and resolves escapes, the inverse of [`crate::value::write_script_string`].
+fn unquote(value: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut result = Vec::with_capacity(value.len());
+    let mut chars = value.iter().copied().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            b'"' => {}
+            b'\\' => {
+                let escaped = chars.next().ok_or("incomplete escape")?;
+                let unescaped = match escaped {
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'a' => b'\x07',
+                    b'b' => b'\x08',
+                    b'f' => b'\x0c',
+                    b'v' => b'\x0b',
+                    b'_' => b' ',
+                    b'0'..=b'9' | b'A'..=b'F' => {
+                        let high = hex_digit(escaped);
+                        match chars.next_if(|&ch| matches!(ch, b'0'..=b'9' | b'A'..=b'F')) {
+                            Some(low) => high << 4 | hex_digit(low),
+                            None => high,
+                        }
+                    }
+                    other => other,
+                };
+                result.push(unescaped);
+            }
+            ch => result.push(ch),
+        }
+    }
+    Ok(result)
+}
+
+fn hex_digit(ch: u8) -> u8 {
+    match ch {
+        b'0'..=b'9' => ch - b'0',
+        _ => ch - b'A' + 10,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::InterfaceListByName;
+
+    #[test]
+    fn test_load_export() {
+        let script = b"# 2024-05-01 12:00:00 by RouterOS 7.14.3\r
+# model = RB5009UG+S+\r
+/interface list\r
+add comment=\"LAN \\\"ports\\\"\" \\\r
+    name=LAN\r
+add name=WAN\r
+set [ find name=WAN ] comment=\"up\\_link\"\r
+/system identity set name=router1\r
+/foo bar\r
+add name=x\r
+";
+        let mut data = Data::default();
+        let warnings = load_export(&mut data, script).expect("cannot load export");
+        assert_eq!(
+            warnings,
+            vec![ExportWarning::UnknownPath {
+                line: 10,
+                path: Box::from(&b"foo/bar"[..]),
+            }]
+        );
+        assert_eq!(&*data.system_identity_cfg.name, b"router1");
+        let lists = data
+            .interface_list_by_name
+            .iter()
+            .map(|InterfaceListByName(list)| (&*list.name, list.comment.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lists,
+            vec![
+                (&b"LAN"[..], Some(&b"LAN \"ports\""[..])),
+                (&b"WAN"[..], Some(&b"up link"[..])),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_entries() {
+        let script = br#"/interface list
+set [ find name=LAN ] comment=early
+add name=LAN
+add name=WAN
+set [ find where name=LAN ] comment="caf\C3\A9"
+set WAN comment=uplink
+"#;
+        let mut data = Data::default();
+        let warnings = load_export(&mut data, script).expect("cannot load export");
+        assert_eq!(
+            warnings,
+            vec![ExportWarning::UnknownEntry {
+                line: 2,
+                path: Box::from(&b"interface/list"[..]),
+            }]
+        );
+        let lists = data
+            .interface_list_by_name
+            .iter()
+            .map(|InterfaceListByName(list)| (&*list.name, list.comment.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lists,
+            vec![
+                (&b"LAN"[..], Some("café".as_bytes())),
+                (&b"WAN"[..], Some(&b"uplink"[..])),
+            ]
+        );
+    }
+}
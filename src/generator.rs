@@ -1,12 +1,29 @@
+use crate::hwconfig::DeviceType;
+use crate::model::{self, Data, IpAddressCfg};
 use crate::resource::{Creatable, CreateHandler, ResourceMutation, ResourceMutationOperation};
 use crate::value::{KeyValuePair, write_script_string};
 use encoding_rs::mem::decode_latin1;
 use std::fmt::Write;
+use thiserror::Error;
+
+/// Seconds the first boot script waits for the ethernet ports to show up
+const FIRST_BOOT_WAIT_SECONDS: usize = 60;
+
+#[derive(Debug, Error)]
+pub enum FirstBootError {
+    #[error("Cannot write script: {0}")]
+    Write(#[from] std::fmt::Error),
+    #[error("{0}")]
+    MissingDependencies(String),
+}
 
 #[derive(Debug)]
 pub struct Generator<'a, W: Write> {
     target: &'a mut W,
     current_path: Option<&'static [u8]>,
+    guarded: bool,
+    compact: bool,
+    annotated: bool,
 }
 
 impl<'a, W: Write> CreateHandler<()> for &mut Generator<'a, W> {
@@ -20,42 +37,188 @@ impl<'a, W: Write> Generator<'a, W> {
         Self {
             target,
             current_path: None,
+            guarded: false,
+            compact: false,
+            annotated: false,
         }
     }
+    /// Wraps every mutation in `:do {...} on-error={...}` and skips adding entries already
+    /// present, so the script can be imported again after a partial failure.
+    ///
+    /// Entries only known by their `.id`, like firewall rules, cannot be found again and are
+    /// added without that check.
+    pub fn guarded(mut self, guarded: bool) -> Self {
+        self.guarded = guarded;
+        self
+    }
+    /// Omits fields which are equal to the RouterOS default, like `/export` without `verbose`.
+    /// Applies to added entries and to updates of fields without a known previous value.
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.compact = compact;
+        self
+    }
+    /// Writes a `#` comment before every command, naming the key, the changed fields with
+    /// their previous values and the [provenance](ResourceMutation::provenance) of the mutation.
+    pub fn annotated(mut self, annotated: bool) -> Self {
+        self.annotated = annotated;
+        self
+    }
     pub fn append_mutation(&mut self, mutation: &ResourceMutation) -> std::fmt::Result {
         match &mutation.operation {
-            ResourceMutationOperation::RemoveByKey(_) => {}
+            ResourceMutationOperation::RemoveByKey(_) | ResourceMutationOperation::Move { .. } => {}
             _ => {
-                if mutation.fields.is_empty() {
+                if mutation.fields.is_empty() && mutation.unset.is_empty() {
                     return Ok(());
                 }
             }
         }
+        if self.guarded {
+            self.append_annotation(mutation)?;
+            return self.append_guarded_mutation(mutation);
+        }
         if Some(mutation.resource) != self.current_path {
             writeln!(self.target, "/{}", decode_latin1(mutation.resource))?;
             self.current_path = Some(mutation.resource);
         }
+        self.append_annotation(mutation)?;
+        self.append_operation(mutation, "")
+    }
+    fn append_annotation(&mut self, mutation: &ResourceMutation) -> std::fmt::Result {
+        if !self.annotated {
+            return Ok(());
+        }
+        let (operation, key) = match &mutation.operation {
+            ResourceMutationOperation::Add => ("add", None),
+            ResourceMutationOperation::RemoveByKey(key) => ("remove", Some(key)),
+            ResourceMutationOperation::UpdateSingle => ("set", None),
+            ResourceMutationOperation::UpdateByKey(key) => ("set", Some(key)),
+            ResourceMutationOperation::Move { key, .. } => ("move", Some(key)),
+        };
+        write!(
+            self.target,
+            "# {operation} /{}",
+            decode_latin1(mutation.resource)
+        )?;
+        if let Some(key) = key {
+            self.target.write_str(" [")?;
+            self.append_field(key)?;
+            self.target.write_char(']')?;
+        }
+        if let Some(provenance) = &mutation.provenance {
+            write!(self.target, ": {}", provenance.replace('\n', " "))?;
+        }
+        writeln!(self.target)?;
+        if matches!(mutation.operation, ResourceMutationOperation::Add) {
+            return Ok(());
+        }
+        for field in &mutation.fields {
+            write!(self.target, "#   {}: ", decode_latin1(field.key))?;
+            if let Some(previous) = mutation.previous.iter().find(|kv| kv.key == field.key) {
+                self.append_value(previous)?;
+                self.target.write_str(" -> ")?;
+            }
+            self.append_value(field)?;
+            writeln!(self.target)?;
+        }
+        for name in &mutation.unset {
+            write!(self.target, "#   {}: ", decode_latin1(name))?;
+            if let Some(previous) = mutation.previous.iter().find(|kv| kv.key == *name) {
+                self.append_value(previous)?;
+                self.target.write_str(" -> ")?;
+            }
+            writeln!(self.target, "(unset)")?;
+        }
+        Ok(())
+    }
+    fn append_guarded_mutation(&mut self, mutation: &ResourceMutation) -> std::fmt::Result {
+        let path = decode_latin1(mutation.resource);
+        self.target.write_str(":do {\n")?;
+        let operation = match &mutation.operation {
+            ResourceMutationOperation::Add => {
+                let key_fields = model::key_fields(mutation.resource);
+                if key_fields.is_empty() {
+                    self.append_operation(mutation, &format!("    /{path} "))?;
+                } else {
+                    write!(self.target, "    :if ([:len [/{path} find ")?;
+                    self.append_key_fields(mutation, key_fields)?;
+                    self.target.write_str("]] = 0) do={\n")?;
+                    self.append_operation(mutation, &format!("        /{path} "))?;
+                    self.target.write_str("    }\n")?;
+                }
+                "add"
+            }
+            ResourceMutationOperation::RemoveByKey(_) => {
+                self.append_operation(mutation, &format!("    /{path} "))?;
+                "remove"
+            }
+            ResourceMutationOperation::UpdateSingle | ResourceMutationOperation::UpdateByKey(_) => {
+                self.append_operation(mutation, &format!("    /{path} "))?;
+                "set"
+            }
+            ResourceMutationOperation::Move { .. } => {
+                self.append_operation(mutation, &format!("    /{path} "))?;
+                "move"
+            }
+        };
+        self.target.write_str("} on-error={ :log error ")?;
+        write_script_string(
+            self.target,
+            format!("mikrotik-model: cannot {operation} /{path}").as_bytes(),
+        )?;
+        self.target.write_str(" }\n")
+    }
+    /// Writes the command lines of `mutation`, each starting with `prefix`.
+    fn append_operation(&mut self, mutation: &ResourceMutation, prefix: &str) -> std::fmt::Result {
         match &mutation.operation {
             ResourceMutationOperation::Add => {
-                write!(self.target, "add ")?;
-                self.append_fields(&mutation.fields)?;
+                write!(self.target, "{prefix}add ")?;
+                self.append_added_fields(mutation)?;
                 writeln!(self.target)?;
             }
             ResourceMutationOperation::RemoveByKey(id_key) => {
-                self.target.write_str("remove [find ")?;
+                write!(self.target, "{prefix}remove [find ")?;
                 self.append_field(id_key)?;
                 self.target.write_str("]\n")?;
             }
             ResourceMutationOperation::UpdateSingle => {
-                self.target.write_str("set ")?;
-                self.append_fields(&mutation.fields)?;
-                writeln!(self.target)?;
+                let fields = self.written_fields(mutation);
+                if !fields.is_empty() {
+                    write!(self.target, "{prefix}set ")?;
+                    self.append_fields(&fields)?;
+                    writeln!(self.target)?;
+                }
+                for name in &mutation.unset {
+                    writeln!(
+                        self.target,
+                        "{prefix}unset value-name={}",
+                        decode_latin1(name)
+                    )?;
+                }
             }
             ResourceMutationOperation::UpdateByKey(id_key) => {
-                self.target.write_str("set [ find ")?;
-                self.append_field(id_key)?;
-                self.target.write_str("] ")?;
-                self.append_fields(&mutation.fields)?;
+                let fields = self.written_fields(mutation);
+                if !fields.is_empty() {
+                    write!(self.target, "{prefix}set [ find ")?;
+                    self.append_field(id_key)?;
+                    self.target.write_str("] ")?;
+                    self.append_fields(&fields)?;
+                    writeln!(self.target)?;
+                }
+                for name in &mutation.unset {
+                    write!(self.target, "{prefix}unset [find ")?;
+                    self.append_field(id_key)?;
+                    writeln!(self.target, "] value-name={}", decode_latin1(name))?;
+                }
+            }
+            ResourceMutationOperation::Move { key, destination } => {
+                write!(self.target, "{prefix}move [find ")?;
+                self.append_field(key)?;
+                self.target.write_char(']')?;
+                if let Some(destination) = destination {
+                    self.target.write_str(" destination=[find ")?;
+                    self.append_field(destination)?;
+                    self.target.write_char(']')?;
+                }
                 writeln!(self.target)?;
             }
         }
@@ -68,19 +231,54 @@ impl<'a, W: Write> Generator<'a, W> {
         }
         Ok(())
     }
-    fn append_field(&mut self, kv: &KeyValuePair) -> std::fmt::Result {
-        write!(self.target, "{}=", decode_latin1(kv.key))?;
-        if !kv.value.is_empty()
-            && kv.value.iter().copied().all(|ch| {
-                ch.is_ascii_alphanumeric() || ch == b'_' || ch == b'-' || ch == b',' || ch == b'*'
+    fn append_added_fields(&mut self, mutation: &ResourceMutation) -> std::fmt::Result {
+        let fields = self.written_fields(mutation);
+        self.append_fields(&fields)
+    }
+    /// The fields of `mutation` to write. In [compact](Generator::compact) mode, fields equal to
+    /// the RouterOS default are left out unless they are keys or were changed from a known
+    /// previous value.
+    fn written_fields<'m>(&self, mutation: &'m ResourceMutation) -> Vec<KeyValuePair<'m>> {
+        let key_fields = model::key_fields(mutation.resource);
+        mutation
+            .fields
+            .iter()
+            .filter(|kv| {
+                !self.compact
+                    || key_fields.contains(&kv.key)
+                    || mutation
+                        .previous
+                        .iter()
+                        .any(|previous| previous.key == kv.key)
+                    || model::default_value(mutation.resource, kv.key)
+                        .is_none_or(|default| default != kv.value)
             })
+            .cloned()
+            .collect()
+    }
+    /// Writes the fields identifying the entry added by `mutation`
+    fn append_key_fields(
+        &mut self,
+        mutation: &ResourceMutation,
+        key_fields: &[&[u8]],
+    ) -> std::fmt::Result {
+        for kv in mutation
+            .fields
+            .iter()
+            .filter(|kv| key_fields.contains(&kv.key))
         {
-            write!(self.target, "{}", decode_latin1(kv.value.as_ref()))?;
-        } else {
-            write_script_string(self.target, &kv.value)?;
+            self.append_field(kv)?;
+            self.target.write_char(' ')?
         }
         Ok(())
     }
+    fn append_field(&mut self, kv: &KeyValuePair) -> std::fmt::Result {
+        write!(self.target, "{}=", decode_latin1(kv.key))?;
+        self.append_value(kv)
+    }
+    fn append_value(&mut self, kv: &KeyValuePair) -> std::fmt::Result {
+        write_value(self.target, &kv.value)
+    }
 }
 
 pub fn generate_cfg(target: &mut impl Write, mutations: &[ResourceMutation]) -> std::fmt::Result {
@@ -105,6 +303,9 @@ pub fn generate_cfg(target: &mut impl Write, mutations: &[ResourceMutation]) ->
                 target.write_str("set ")?;
                 append_fields(target, mutation)?;
                 writeln!(target)?;
+                for name in &mutation.unset {
+                    writeln!(target, "unset value-name={}", decode_latin1(name))?;
+                }
             }
             ResourceMutationOperation::UpdateByKey(id_key) => {
                 target.write_str("set [ find ")?;
@@ -112,12 +313,64 @@ pub fn generate_cfg(target: &mut impl Write, mutations: &[ResourceMutation]) ->
                 target.write_str("] ")?;
                 append_fields(target, mutation)?;
                 writeln!(target)?;
+                for name in &mutation.unset {
+                    target.write_str("unset [find ")?;
+                    append_field(target, id_key)?;
+                    writeln!(target, "] value-name={}", decode_latin1(name))?;
+                }
+            }
+            ResourceMutationOperation::Move { key, destination } => {
+                target.write_str("move [find ")?;
+                append_field(target, key)?;
+                target.write_char(']')?;
+                if let Some(destination) = destination {
+                    target.write_str(" destination=[find ")?;
+                    append_field(target, destination)?;
+                    target.write_char(']')?;
+                }
+                writeln!(target)?;
             }
         }
     }
     Ok(())
 }
 
+/// Renders `data` as complete configuration of a freshly reset `device_type`, e.g. to be passed
+/// to netinstall with `-s`.
+///
+/// The script first waits until all ethernet ports of the device are available and then adds
+/// `fallback_address` before anything else, so the device stays reachable if a later command
+/// fails.
+pub fn generate_first_boot(
+    target: &mut impl Write,
+    device_type: DeviceType,
+    data: &Data,
+    fallback_address: Option<&IpAddressCfg>,
+) -> Result<(), FirstBootError> {
+    let base = device_type.generate_empty_data();
+    let mutations = data.full_config_mutations(&base);
+    let provided = base
+        .full_config_mutations(&base)
+        .into_iter()
+        .flat_map(|mutation| mutation.provides.into_vec());
+    let sorted = ResourceMutation::sort_mutations_with_provided_dependencies(&mutations, provided)
+        .map_err(|error| FirstBootError::MissingDependencies(error.to_string()))?;
+
+    writeln!(
+        target,
+        "{{\n    :local waited 0\n    :while ([:len [/interface/ethernet find]] < {} && $waited < {FIRST_BOOT_WAIT_SECONDS}) do={{\n        :delay 1s\n        :set waited ($waited + 1)\n    }}\n}}",
+        device_type.build_ethernet_ports().len()
+    )?;
+    let mut generator = Generator::new(target);
+    if let Some(address) = fallback_address {
+        generator.append_mutation(&address.calculate_create())?;
+    }
+    for mutation in sorted {
+        generator.append_mutation(mutation)?;
+    }
+    Ok(())
+}
+
 fn append_fields<W: Write>(target: &mut W, mutation: &ResourceMutation) -> std::fmt::Result {
     for kv in &mutation.fields {
         append_field(target, kv)?;
@@ -128,16 +381,231 @@ fn append_fields<W: Write>(target: &mut W, mutation: &ResourceMutation) -> std::
 
 fn append_field(target: &mut impl Write, kv: &KeyValuePair) -> std::fmt::Result {
     write!(target, "{}=", decode_latin1(kv.key))?;
-    if !kv.value.is_empty()
-        && kv
-            .value
-            .iter()
-            .copied()
-            .all(|ch| ch.is_ascii_alphanumeric() || ch == b'_' || ch == b'-' || ch == b',')
+    write_value(target, &kv.value)
+}
+
+/// Writes `value` as is if it is a plain word (like `ether1`, `a,b` or `*1F`), quoted otherwise
+pub(crate) fn write_value(target: &mut impl Write, value: &[u8]) -> std::fmt::Result {
+    if !value.is_empty()
+        && value.iter().copied().all(|ch| {
+            ch.is_ascii_alphanumeric() || ch == b'_' || ch == b'-' || ch == b',' || ch == b'*'
+        })
     {
-        write!(target, "{}", decode_latin1(&kv.value))?;
+        write!(target, "{}", decode_latin1(value))
     } else {
-        write_script_string(target, &kv.value)?;
+        write_script_string(target, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        InterfaceBridgeCfg, InterfaceVlanByName, InterfaceVlanCfg, Ipv6FirewallFilterById,
+        Ipv6FirewallFilterCfgBuilder, SystemIdentityCfg,
+    };
+    use crate::resource::{DeserializeRosBuilder, Updatable, generate_ordered_by_id};
+    use crate::value::Id;
+    use ipnet::Ipv4Net;
+    use std::borrow::Cow;
+    use std::time::Duration;
+
+    #[test]
+    fn test_guarded_script() {
+        let current = SystemIdentityCfg {
+            name: b"old-name".into(),
+        };
+        let target = SystemIdentityCfg {
+            name: b"new-name".into(),
+        };
+        let vlan = InterfaceVlanCfg {
+            name: b"vlan-10".into(),
+            vlan_id: 10,
+            ..InterfaceVlanCfg::default()
+        };
+        let mut script = String::new();
+        let mut generator = Generator::new(&mut script).guarded(true);
+        generator
+            .append_mutation(&target.calculate_update(&current))
+            .unwrap();
+        generator.append_mutation(&vlan.calculate_create()).unwrap();
+        assert!(script.starts_with(
+            ":do {\n    /system/identity set name=new-name \n} on-error={ :log error \"mikrotik-model: cannot set /system/identity\" }\n:do {\n    :if ([:len [/interface/vlan find "
+        ));
+        assert!(script.contains("find name=vlan-10 ]] = 0) do={\n        /interface/vlan add "));
+        assert!(script.ends_with(
+            "\n    }\n} on-error={ :log error \"mikrotik-model: cannot add /interface/vlan\" }\n"
+        ));
+    }
+
+    #[test]
+    fn test_guarded_add_finds_by_key() {
+        let bridge = InterfaceBridgeCfg {
+            name: b"br0".into(),
+            ageing_time: Duration::from_secs(600),
+            ..InterfaceBridgeCfg::default()
+        };
+        let mut script = String::new();
+        Generator::new(&mut script)
+            .guarded(true)
+            .append_mutation(&bridge.calculate_create())
+            .unwrap();
+        assert!(script.contains(
+            ":if ([:len [/interface/bridge find name=br0 ]] = 0) do={\n        /interface/bridge add "
+        ));
+        assert!(script.contains(" ageing-time="));
+    }
+
+    #[test]
+    fn test_guarded_ordered_add() {
+        let rule = |action: &[u8]| {
+            let mut builder = Ipv6FirewallFilterCfgBuilder::default();
+            builder.append_field(b"chain", Some(b"forward"));
+            builder.append_field(b"action", Some(action));
+            builder.build().expect("incomplete rule")
+        };
+        let current = [Ipv6FirewallFilterById {
+            id: Id(3),
+            data: rule(b"drop"),
+        }];
+        let target = [rule(b"accept"), rule(b"drop")];
+        let mutations =
+            generate_ordered_by_id(&current, target.iter().map(Cow::Borrowed)).collect::<Vec<_>>();
+        let mut script = String::new();
+        let mut generator = Generator::new(&mut script).guarded(true);
+        for mutation in &mutations {
+            generator.append_mutation(mutation).unwrap();
+        }
+        // a rule is only known by its `.id`, so it is added without checking for it first
+        assert_eq!(
+            script,
+            ":do {\n    /ipv6/firewall/filter add action=accept chain=forward place-before=*3 \n\
+             } on-error={ :log error \"mikrotik-model: cannot add /ipv6/firewall/filter\" }\n"
+        );
+    }
+
+    #[test]
+    fn test_compact_script() {
+        let vlan = InterfaceVlanCfg {
+            name: b"vlan-10".into(),
+            vlan_id: 10,
+            interface: b"ether1".into(),
+            ..InterfaceVlanCfg::default()
+        };
+        let mutation = vlan.calculate_create();
+        let mut verbose = String::new();
+        Generator::new(&mut verbose)
+            .append_mutation(&mutation)
+            .unwrap();
+        let mut compact = String::new();
+        Generator::new(&mut compact)
+            .compact(true)
+            .append_mutation(&mutation)
+            .unwrap();
+        assert!(verbose.contains(" loop-protect-send-interval=5s "));
+        assert!(verbose.contains(" mtu=1500 "));
+        assert!(!compact.contains("loop-protect-send-interval="));
+        assert!(!compact.contains("loop-protect-disable-time="));
+        assert!(!compact.contains(" mtu="));
+        assert!(!compact.contains("disabled="));
+        assert!(compact.contains(" name=vlan-10 "));
+        assert!(compact.contains(" vlan-id=10 "));
+        assert!(compact.contains(" interface=ether1 "));
+        // no `default=` declared, so it is written even though it matches the `Default` impl
+        assert!(compact.contains(" l2mtu=1556 "));
+
+        let bridge = InterfaceBridgeCfg {
+            name: b"br0".into(),
+            ..InterfaceBridgeCfg::default()
+        };
+        let mut compact = String::new();
+        Generator::new(&mut compact)
+            .compact(true)
+            .append_mutation(&bridge.calculate_create())
+            .unwrap();
+        assert_eq!(compact, "/interface/bridge\nadd name=br0 \n");
+    }
+    #[test]
+    fn test_compact_set() {
+        let current = InterfaceVlanByName(InterfaceVlanCfg {
+            name: b"vlan-10".into(),
+            vlan_id: 10,
+            mtu: Some(9000),
+            ..InterfaceVlanCfg::default()
+        });
+        let target = InterfaceVlanByName(InterfaceVlanCfg {
+            vlan_id: 20,
+            mtu: Some(1500),
+            ..current.0.clone()
+        });
+        let mut mutation = target.calculate_update(&current);
+        let mut script = String::new();
+        Generator::new(&mut script)
+            .compact(true)
+            .append_mutation(&mutation)
+            .unwrap();
+        assert!(script.contains(" mtu=1500 "));
+
+        // without a known previous value the device is expected to still have the default
+        mutation.previous = Box::new([]);
+        let mut script = String::new();
+        Generator::new(&mut script)
+            .compact(true)
+            .append_mutation(&mutation)
+            .unwrap();
+        assert!(script.contains(" vlan-id=20 "));
+        assert!(!script.contains("mtu="));
+    }
+    #[test]
+    fn test_annotated_script() {
+        let current = SystemIdentityCfg {
+            name: b"old-name".into(),
+        };
+        let target = SystemIdentityCfg {
+            name: b"new name".into(),
+        };
+        let mut script = String::new();
+        Generator::new(&mut script)
+            .annotated(true)
+            .append_mutation(
+                &target
+                    .calculate_update(&current)
+                    .with_provenance("managed by intent: identity"),
+            )
+            .unwrap();
+        assert_eq!(
+            script,
+            "/system/identity\n# set /system/identity: managed by intent: identity\n#   name: old-name -> \"new name\"\nset name=\"new name\" \n"
+        );
+    }
+    #[test]
+    fn test_first_boot_script() {
+        let device_type = DeviceType::Crs32624g2splus;
+        let mut data = device_type.generate_empty_data();
+        data.system_identity_cfg.name = b"switch".into();
+        data.interface_ethernet_by_default_name[0].data.name = b"uplink".into();
+        data.interface_vlan_cfg.push(InterfaceVlanCfg {
+            name: b"vlan-10".into(),
+            vlan_id: 10,
+            interface: b"uplink".into(),
+            ..InterfaceVlanCfg::default()
+        });
+        let fallback = IpAddressCfg {
+            address: "192.168.88.2/24".parse::<Ipv4Net>().unwrap(),
+            interface: b"ether24".into(),
+            comment: None,
+        };
+        let mut script = String::new();
+        generate_first_boot(&mut script, device_type, &data, Some(&fallback)).unwrap();
+        assert!(script.starts_with(
+            "{\n    :local waited 0\n    :while ([:len [/interface/ethernet find]] < 26 && $waited < 60) do={\n"
+        ));
+        let fallback_position = script.find("/ip/address\nadd ").unwrap();
+        let rename_position = script.find("name=uplink").unwrap();
+        let vlan_position = script.find("/interface/vlan\nadd ").unwrap();
+        assert!(fallback_position < rename_position);
+        assert!(rename_position < vlan_position);
+        assert!(script.contains("/system/identity\nset name=switch \n"));
+        assert_eq!(script.matches("set [ find default-name=").count(), 1);
     }
-    Ok(())
 }
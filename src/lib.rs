@@ -1,17 +1,26 @@
 use crate::{model::Resource, resource::SentenceResult};
+// lets the tests use the paths generated by `mikrotik_model!`
+extern crate self as mikrotik_model;
 use serde::Deserialize;
 
 pub mod ascii;
+pub mod convergence;
+pub mod desired_state;
 pub mod error;
+pub mod export;
 pub mod generator;
 pub mod hwconfig;
 pub mod model;
+pub mod plan;
+pub mod profile;
 pub mod repository;
 pub mod resource;
+pub mod snapshot;
 mod util;
 pub mod value;
 pub use mac_address::MacAddress;
 pub use mikrotik_model_generator_macro::mikrotik_model;
+pub use serde;
 pub mod mikrotik_api {
     pub use mikrotik_api::prelude::*;
 }
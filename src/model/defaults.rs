@@ -42,6 +42,35 @@ impl Default for SystemRouterboardSettingsCfg {
         }
     }
 }
+impl Default for IpDnsStaticCfg {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[allow(clippy::derivable_impls)]
+impl Default for IpDnsCfg {
+    fn default() -> Self {
+        IpDnsCfg {
+            address_list_extra_time: None,
+            allow_remote_requests: false,
+            cache_max_ttl: None,
+            cache_size: None,
+            doh_max_concurrent_queries: None,
+            doh_max_server_connections: None,
+            doh_timeout: None,
+            max_concurrent_queries: None,
+            max_concurrent_tcp_sessions: None,
+            max_udp_packet_size: None,
+            mdns_repeat_ifaces: Default::default(),
+            query_server_timeout: None,
+            query_total_timeout: None,
+            servers: Vec::new(),
+            use_doh_server: None,
+            verify_doh_cert: None,
+            vrf: None,
+        }
+    }
+}
 #[allow(clippy::derivable_impls)]
 impl Default for InterfaceWifiRadioSettingsCfg {
     fn default() -> Self {
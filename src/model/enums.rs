@@ -1,4 +1,8 @@
-use crate::model::YesNo;
+use crate::model::{IpProtocol, YesNo};
+use crate::value::NameOrNumber;
+
+/// IP protocol by name or number, as accepted by the firewall `protocol` matchers
+pub type IpProtocolOrNumber = NameOrNumber<IpProtocol>;
 
 impl From<bool> for YesNo {
     fn from(value: bool) -> Self {
@@ -2,3 +2,94 @@ include!(concat!(env!("OUT_DIR"), "/mikrotik-model.rs"));
 
 mod defaults;
 mod enums;
+
+pub use enums::IpProtocolOrNumber;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource::{AppendFieldResult, DeserializeRosBuilder};
+    use crate::value::{NameOrNumber, Negatable, ParseRosValueResult, PossibleRangeDash, RosValue};
+    use std::collections::BTreeSet;
+    use std::net::IpAddr;
+
+    fn parse<V: RosValue>(value: &[u8]) -> V {
+        match V::parse_ros(value) {
+            ParseRosValueResult::Value(v) => v,
+            _ => panic!("cannot parse {}", String::from_utf8_lossy(value)),
+        }
+    }
+
+    #[test]
+    fn test_firewall_values() {
+        let protocol: Negatable<IpProtocolOrNumber> = parse(b"tcp");
+        assert_eq!(protocol, Negatable::is(NameOrNumber::Name(IpProtocol::Tcp)));
+        let protocol: Negatable<IpProtocolOrNumber> = parse(b"!47");
+        assert_eq!(protocol, Negatable::not(NameOrNumber::Number(47)));
+        assert_eq!(protocol.encode_ros().as_ref(), b"!47");
+        assert!(matches!(
+            IpProtocolOrNumber::parse_ros(b"256"),
+            ParseRosValueResult::Invalid
+        ));
+
+        let states: Negatable<BTreeSet<FirewallConnectionState>> = parse(b"established,related");
+        assert_eq!(
+            states.value.into_iter().collect::<Vec<_>>(),
+            [
+                FirewallConnectionState::Established,
+                FirewallConnectionState::Related
+            ]
+        );
+        assert_eq!(
+            parse::<IpFirewallFilterAction>(b"drop"),
+            IpFirewallFilterAction::Drop
+        );
+    }
+
+    #[test]
+    fn test_ipv6_firewall_values() {
+        let mut rule = Ipv6FirewallFilterCfgBuilder::default();
+        for (key, value) in [
+            (&b"dst-port"[..], &b"!22,8000-8080"[..]),
+            (b"packet-size", b"64-128"),
+            (b"protocol", b"icmpv6"),
+        ] {
+            assert!(matches!(
+                rule.append_field(key, Some(value)),
+                AppendFieldResult::Appended
+            ));
+        }
+        let dst_port = rule.dst_port;
+        assert!(dst_port.negated);
+        assert_eq!(
+            dst_port.value,
+            BTreeSet::from([
+                PossibleRangeDash::Single(22),
+                PossibleRangeDash::Range {
+                    start: 8000,
+                    end: 8080
+                }
+            ])
+        );
+        assert_eq!(
+            rule.protocol,
+            Some(Negatable::is(NameOrNumber::Name(IpProtocol::Icmpv6)))
+        );
+    }
+
+    #[test]
+    fn test_dns_values() {
+        let servers: Vec<IpAddr> = parse(b"9.9.9.9,1.1.1.1");
+        assert_eq!(
+            servers,
+            [
+                "9.9.9.9".parse::<IpAddr>().unwrap(),
+                "1.1.1.1".parse().unwrap()
+            ]
+        );
+        assert_eq!(servers.encode_ros().as_ref(), b"9.9.9.9,1.1.1.1");
+        let static_type: IpDnsStaticType = parse(b"FWD");
+        assert_eq!(static_type, IpDnsStaticType::Fwd);
+        assert_eq!(static_type.encode_ros().as_ref(), b"FWD");
+    }
+}
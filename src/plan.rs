@@ -0,0 +1,182 @@
+use crate::generator::write_value;
+use crate::resource::{ResourceMutation, ResourceMutationOperation};
+use encoding_rs::mem::decode_latin1;
+use std::fmt::{Display, Formatter, Write};
+
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+/// Human-readable overview of a list of mutations, showing the previous and new value of
+/// every changed field.
+///
+/// ```text
+/// + interface/vlan
+///     name: vlan-10
+///     vlan-id: 10
+/// ~ interface/vlan [name=vlan-20]
+///     vlan-id: 21 → 20
+/// - interface/vlan [name=vlan-30]
+/// > ipv6/firewall/filter [.id=*3] before [.id=*1]
+/// ```
+#[derive(Debug)]
+pub struct Plan<'p, 'a> {
+    mutations: Box<[&'p ResourceMutation<'a>]>,
+    colored: bool,
+}
+
+impl<'p, 'a> Plan<'p, 'a> {
+    pub fn new(mutations: impl IntoIterator<Item = &'p ResourceMutation<'a>>) -> Self {
+        Self {
+            mutations: mutations.into_iter().collect(),
+            colored: false,
+        }
+    }
+    /// enable ansi colors for terminal output
+    pub fn colored(mut self, colored: bool) -> Self {
+        self.colored = colored;
+        self
+    }
+    fn write_mutation(
+        &self,
+        f: &mut Formatter<'_>,
+        mutation: &ResourceMutation,
+    ) -> std::fmt::Result {
+        let (symbol, color, key, destination) = match &mutation.operation {
+            ResourceMutationOperation::Add => ('+', GREEN, None, None),
+            ResourceMutationOperation::RemoveByKey(key) => ('-', RED, Some(key), None),
+            ResourceMutationOperation::UpdateSingle => ('~', YELLOW, None, None),
+            ResourceMutationOperation::UpdateByKey(key) => ('~', YELLOW, Some(key), None),
+            ResourceMutationOperation::Move { key, destination } => {
+                ('>', YELLOW, Some(key), Some(destination))
+            }
+        };
+        if self.colored {
+            f.write_str(color)?;
+        }
+        write!(f, "{symbol} {}", decode_latin1(mutation.resource))?;
+        if let Some(key) = key {
+            f.write_str(" [")?;
+            write!(f, "{}=", decode_latin1(key.key))?;
+            write_value(f, &key.value)?;
+            f.write_char(']')?;
+        }
+        match destination {
+            Some(Some(destination)) => {
+                write!(f, " before [{}=", decode_latin1(destination.key))?;
+                write_value(f, &destination.value)?;
+                f.write_char(']')?;
+            }
+            Some(None) => f.write_str(" to end")?,
+            None => {}
+        }
+        if self.colored {
+            f.write_str(RESET)?;
+        }
+        writeln!(f)?;
+        for field in &mutation.fields {
+            write!(f, "    {}: ", decode_latin1(field.key))?;
+            if !matches!(mutation.operation, ResourceMutationOperation::Add)
+                && let Some(previous) = mutation.previous.iter().find(|kv| kv.key == field.key)
+            {
+                if self.colored {
+                    f.write_str(RED)?;
+                }
+                write_value(f, &previous.value)?;
+                if self.colored {
+                    f.write_str(RESET)?;
+                }
+                f.write_str(" → ")?;
+            }
+            if self.colored {
+                f.write_str(GREEN)?;
+            }
+            write_value(f, &field.value)?;
+            if self.colored {
+                f.write_str(RESET)?;
+            }
+            writeln!(f)?;
+        }
+        for name in &mutation.unset {
+            write!(f, "    {}: ", decode_latin1(name))?;
+            if let Some(previous) = mutation.previous.iter().find(|kv| kv.key == *name) {
+                if self.colored {
+                    f.write_str(RED)?;
+                }
+                write_value(f, &previous.value)?;
+                if self.colored {
+                    f.write_str(RESET)?;
+                }
+                f.write_str(" → ")?;
+            }
+            writeln!(f, "(unset)")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Plan<'_, '_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for mutation in &self.mutations {
+            match &mutation.operation {
+                ResourceMutationOperation::RemoveByKey(_)
+                | ResourceMutationOperation::Move { .. } => {}
+                _ => {
+                    if mutation.fields.is_empty() && mutation.unset.is_empty() {
+                        continue;
+                    }
+                }
+            }
+            self.write_mutation(f, mutation)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{InterfaceVlanCfg, SystemIdentityCfg};
+    use crate::resource::{Creatable, Updatable};
+    use crate::value::KeyValuePair;
+    use std::borrow::Cow;
+
+    #[test]
+    fn test_plan() {
+        let current_identity = SystemIdentityCfg {
+            name: b"old-name".into(),
+        };
+        let target_identity = SystemIdentityCfg {
+            name: b"new name".into(),
+        };
+        let vlan = InterfaceVlanCfg {
+            name: b"vlan-10".into(),
+            vlan_id: 10,
+            ..InterfaceVlanCfg::default()
+        };
+        let mutations = [
+            target_identity.calculate_update(&current_identity),
+            vlan.calculate_create(),
+            ResourceMutation {
+                resource: b"interface/vlan",
+                operation: ResourceMutationOperation::RemoveByKey(KeyValuePair {
+                    key: b"name",
+                    value: Cow::Borrowed(b"vlan-30"),
+                }),
+                fields: Box::new([]),
+                previous: Box::new([]),
+                unset: Box::new([]),
+                provenance: None,
+                depends: Box::new([]),
+                provides: Box::new([]),
+            },
+        ];
+        let plan = Plan::new(&mutations).to_string();
+        assert!(plan.starts_with("~ system/identity\n    name: old-name → \"new name\"\n"));
+        assert!(plan.contains("+ interface/vlan\n"));
+        assert!(plan.contains("    name: vlan-10\n"));
+        assert!(plan.contains("    vlan-id: 10\n"));
+        assert!(plan.ends_with("- interface/vlan [name=vlan-30]\n"));
+    }
+}
@@ -0,0 +1,569 @@
+use crate::resource::{ResourceMutation, ResourceMutationOperation};
+use encoding_rs::mem::decode_latin1;
+use serde::de::DeserializeOwned;
+use serde_yaml::{Mapping, Value};
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Tag removing a field, an entry of a collection or a whole collection: `comment: !delete`
+pub const DELETE_TAG: &str = "delete";
+/// Tag replacing a single resource or a collection instead of merging it: `vlans: !replace [...]`
+pub const REPLACE_TAG: &str = "replace";
+
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("layer {layer}: {source}")]
+    Parse {
+        layer: String,
+        #[source]
+        source: serde_yaml::Error,
+    },
+    #[error("layer {layer}: {path}: {message}")]
+    InvalidLayer {
+        layer: String,
+        path: String,
+        message: String,
+    },
+    #[error("merged profile: {0}")]
+    Merged(#[source] serde_yaml::Error),
+}
+
+/// A top level field of a `*Target` struct generated by `mikrotik_model!`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Collection {
+    pub field: &'static str,
+    pub resource: &'static [u8],
+    /// RouterOS names of the fields identifying an entry, `None` for single resources, empty
+    /// for lists whose entries are only appended
+    pub keys: Option<&'static [&'static str]>,
+}
+
+/// Target that can be merged from several layers, implemented by `mikrotik_model!`.
+pub trait LayeredTarget: DeserializeOwned {
+    const COLLECTIONS: &'static [Collection];
+}
+
+/// One partial desired-state document, in the format read by
+/// [`load_yaml`](crate::desired_state::load_yaml).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub content: Mapping,
+}
+
+impl Layer {
+    pub fn parse(name: impl Into<String>, yaml: &str) -> Result<Self, ProfileError> {
+        let name = name.into();
+        match serde_yaml::from_str::<Option<Mapping>>(yaml) {
+            Ok(content) => Ok(Self {
+                name,
+                content: content.unwrap_or_default(),
+            }),
+            Err(source) => Err(ProfileError::Parse {
+                layer: name,
+                source,
+            }),
+        }
+    }
+    pub fn load(name: impl Into<String>, path: impl AsRef<Path>) -> Result<Self, ProfileError> {
+        let path = path.as_ref();
+        let yaml = fs::read_to_string(path).map_err(|source| ProfileError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(name, &yaml)
+    }
+}
+
+/// Layer that set a field last
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldOrigin {
+    pub layer: String,
+    /// the field or entry was removed by a deletion marker
+    pub deleted: bool,
+}
+
+/// Origin of every merged field, keyed by paths like `bridge[name=bridge1].mtu` or
+/// `identity.name`; unkeyed entries are addressed by position: `filter[#3].chain`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Provenance {
+    pub fields: BTreeMap<String, FieldOrigin>,
+    collections: &'static [Collection],
+}
+
+/// Merges `layers` in the given order, later layers override fields of earlier ones.
+///
+/// Single resources are merged field by field, collections entry by entry as identified by
+/// their key fields. A collection missing in all layers is left empty.
+pub fn merge<T: LayeredTarget>(layers: &[Layer]) -> Result<(T, Provenance), ProfileError> {
+    let mut merged = Mapping::new();
+    for collection in T::COLLECTIONS {
+        if collection.keys.is_some() {
+            merged.insert(collection.field.into(), Value::Sequence(Vec::new()));
+        }
+    }
+    let mut provenance = Provenance {
+        fields: BTreeMap::new(),
+        collections: T::COLLECTIONS,
+    };
+    for layer in layers {
+        let mut merger = LayerMerger {
+            layer: &layer.name,
+            provenance: &mut provenance,
+        };
+        for (field, value) in &layer.content {
+            let collection = field
+                .as_str()
+                .and_then(|field| T::COLLECTIONS.iter().find(|c| c.field == field))
+                .ok_or_else(|| merger.error(&scalar_text(field), "unknown field"))?;
+            merger.merge_collection(&mut merged, collection, value)?;
+        }
+    }
+    let target = serde_yaml::from_value(Value::Mapping(merged)).map_err(ProfileError::Merged)?;
+    Ok((target, provenance))
+}
+
+struct LayerMerger<'l> {
+    layer: &'l str,
+    provenance: &'l mut Provenance,
+}
+
+enum Marker<'v> {
+    Plain(&'v Value),
+    Delete(&'v Value),
+    Replace(&'v Value),
+}
+
+impl LayerMerger<'_> {
+    fn error(&self, path: &str, message: impl Into<String>) -> ProfileError {
+        ProfileError::InvalidLayer {
+            layer: self.layer.to_string(),
+            path: path.to_string(),
+            message: message.into(),
+        }
+    }
+    fn marker<'v>(&self, path: &str, value: &'v Value) -> Result<Marker<'v>, ProfileError> {
+        match value {
+            Value::Tagged(tagged) if tagged.tag == DELETE_TAG => Ok(Marker::Delete(&tagged.value)),
+            Value::Tagged(tagged) if tagged.tag == REPLACE_TAG => {
+                Ok(Marker::Replace(&tagged.value))
+            }
+            Value::Tagged(tagged) => Err(self.error(path, format!("unknown tag {}", tagged.tag))),
+            value => Ok(Marker::Plain(value)),
+        }
+    }
+    fn record(&mut self, path: String, deleted: bool) {
+        self.provenance.fields.insert(
+            path,
+            FieldOrigin {
+                layer: self.layer.to_string(),
+                deleted,
+            },
+        );
+    }
+    fn forget(&mut self, prefix: &str) {
+        self.provenance.fields.retain(|path, _| {
+            !path
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+        });
+    }
+
+    fn merge_collection(
+        &mut self,
+        merged: &mut Mapping,
+        collection: &Collection,
+        value: &Value,
+    ) -> Result<(), ProfileError> {
+        let path = collection.field;
+        let marker = self.marker(path, value)?;
+        let Some(keys) = collection.keys else {
+            return match marker {
+                Marker::Delete(_) => Err(self.error(path, "single resources cannot be deleted")),
+                Marker::Replace(value) | Marker::Plain(value) => {
+                    let replace = matches!(marker, Marker::Replace(_));
+                    let fields = self.expect_mapping(path, value)?;
+                    if replace || !merged.contains_key(path) {
+                        self.forget(path);
+                        merged.insert(path.into(), Value::Mapping(Mapping::new()));
+                    }
+                    let Some(Value::Mapping(target)) = merged.get_mut(path) else {
+                        unreachable!("single resource is a mapping")
+                    };
+                    self.merge_fields(target, fields, path)
+                }
+            };
+        };
+        let entries = match marker {
+            Marker::Delete(_) => {
+                self.forget(path);
+                merged.insert(path.into(), Value::Sequence(Vec::new()));
+                self.record(path.to_string(), true);
+                return Ok(());
+            }
+            Marker::Replace(entries) => {
+                self.forget(path);
+                merged.insert(path.into(), Value::Sequence(Vec::new()));
+                entries
+            }
+            Marker::Plain(entries) => entries,
+        };
+        let Value::Sequence(entries) = entries else {
+            return Err(self.error(path, "expected a list of entries"));
+        };
+        let Some(Value::Sequence(target)) = merged.get_mut(path) else {
+            unreachable!("collections are initialized as list")
+        };
+        for entry in entries {
+            self.merge_entry(target, path, keys, entry)?;
+        }
+        Ok(())
+    }
+
+    fn merge_entry(
+        &mut self,
+        target: &mut Vec<Value>,
+        path: &str,
+        keys: &[&str],
+        entry: &Value,
+    ) -> Result<(), ProfileError> {
+        let marker = self.marker(path, entry)?;
+        let (Marker::Plain(entry) | Marker::Delete(entry) | Marker::Replace(entry)) = marker;
+        let fields = self.expect_mapping(path, entry)?;
+        if keys.is_empty() {
+            if let Marker::Delete(_) = marker {
+                return Err(self.error(path, "entries without key cannot be deleted"));
+            }
+            let entry_path = format!("{path}[#{}]", target.len());
+            target.push(Value::Mapping(Mapping::new()));
+            let Some(Value::Mapping(new_entry)) = target.last_mut() else {
+                unreachable!("entry just added")
+            };
+            return self.merge_fields(new_entry, fields, &entry_path);
+        }
+        let key_values = keys
+            .iter()
+            .map(|key| {
+                fields
+                    .get(key)
+                    .map(scalar_text)
+                    .ok_or_else(|| self.error(path, format!("entry without key field {key}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let entry_path = entry_path(path, keys, &key_values);
+        let existing = target.iter().position(|candidate| {
+            keys.iter()
+                .zip(&key_values)
+                .all(|(key, value)| candidate.get(key).map(scalar_text).as_ref() == Some(value))
+        });
+        match (marker, existing) {
+            (Marker::Delete(_), existing) => {
+                if let Some(idx) = existing {
+                    target.remove(idx);
+                }
+                self.forget(&entry_path);
+                self.record(entry_path, true);
+                Ok(())
+            }
+            (marker, Some(idx)) => {
+                if let Marker::Replace(_) = marker {
+                    self.forget(&entry_path);
+                    target[idx] = Value::Mapping(Mapping::new());
+                }
+                let Value::Mapping(existing) = &mut target[idx] else {
+                    unreachable!("entries are mappings")
+                };
+                self.merge_fields(existing, fields, &entry_path)
+            }
+            (_, None) => {
+                target.push(Value::Mapping(Mapping::new()));
+                let Some(Value::Mapping(new_entry)) = target.last_mut() else {
+                    unreachable!("entry just added")
+                };
+                self.merge_fields(new_entry, fields, &entry_path)
+            }
+        }
+    }
+
+    fn merge_fields(
+        &mut self,
+        target: &mut Mapping,
+        fields: &Mapping,
+        path: &str,
+    ) -> Result<(), ProfileError> {
+        for (field, value) in fields {
+            let field_path = format!("{path}.{}", scalar_text(field));
+            match self.marker(&field_path, value)? {
+                Marker::Delete(_) => {
+                    target.remove(field);
+                    self.record(field_path, true);
+                }
+                Marker::Replace(value) | Marker::Plain(value) => {
+                    target.insert(field.clone(), value.clone());
+                    self.record(field_path, false);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn expect_mapping<'v>(
+        &self,
+        path: &str,
+        value: &'v Value,
+    ) -> Result<&'v Mapping, ProfileError> {
+        match value {
+            Value::Mapping(mapping) => Ok(mapping),
+            _ => Err(self.error(path, "expected a map of fields")),
+        }
+    }
+}
+
+fn entry_path(path: &str, keys: &[&str], values: &[String]) -> String {
+    let key = keys
+        .iter()
+        .zip(values)
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{path}[{key}]")
+}
+
+fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Bool(value) => value.to_string(),
+        Value::Number(value) => value.to_string(),
+        Value::Tagged(tagged) => scalar_text(&tagged.value),
+        value => serde_yaml::to_string(value)
+            .unwrap_or_default()
+            .trim_end()
+            .to_string(),
+    }
+}
+
+impl Provenance {
+    pub fn origin(&self, path: &str) -> Option<&FieldOrigin> {
+        self.fields.get(path)
+    }
+
+    /// Attaches the layers that set the fields of `mutation` as its
+    /// [provenance](ResourceMutation::provenance), if the entry can be identified.
+    ///
+    /// Entries are identified by their key fields, which are only known for additions and for
+    /// updates addressed by the key itself.
+    pub fn annotate<'a>(&self, mutation: ResourceMutation<'a>) -> ResourceMutation<'a> {
+        let operation_key = match &mutation.operation {
+            ResourceMutationOperation::RemoveByKey(key)
+            | ResourceMutationOperation::UpdateByKey(key)
+            | ResourceMutationOperation::Move { key, .. } => Some(key),
+            ResourceMutationOperation::Add | ResourceMutationOperation::UpdateSingle => None,
+        };
+        let key_value = |name: &str| {
+            operation_key
+                .into_iter()
+                .chain(mutation.fields.iter())
+                .find(|field| field.key == name.as_bytes())
+                .map(|field| decode_latin1(&field.value).into_owned())
+        };
+        let Some(path) = self
+            .collections
+            .iter()
+            .filter(|collection| collection.resource == mutation.resource)
+            .find_map(|collection| match collection.keys {
+                None => Some(collection.field.to_string()),
+                Some([]) => None,
+                Some(keys) => {
+                    let values = keys
+                        .iter()
+                        .map(|key| key_value(key))
+                        .collect::<Option<Vec<_>>>()?;
+                    Some(entry_path(collection.field, keys, &values))
+                }
+            })
+        else {
+            return mutation;
+        };
+        let mut by_layer = BTreeMap::<&str, Vec<String>>::new();
+        let changed = mutation
+            .fields
+            .iter()
+            .map(|field| field.key)
+            .chain(mutation.unset.iter().copied());
+        for field in changed {
+            let field = decode_latin1(field);
+            if let Some(origin) = self.fields.get(&format!("{path}.{field}")) {
+                by_layer
+                    .entry(&origin.layer)
+                    .or_default()
+                    .push(field.into_owned());
+            }
+        }
+        if by_layer.is_empty() {
+            return mutation;
+        }
+        let description = by_layer
+            .into_iter()
+            .map(|(layer, fields)| format!("{layer} ({})", fields.join(", ")))
+            .collect::<Vec<_>>()
+            .join(", ");
+        mutation.with_provenance(format!("set by {description}"))
+    }
+}
+
+impl Display for Provenance {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (path, origin) in &self.fields {
+            if origin.deleted {
+                writeln!(f, "{path}: deleted by {}", origin.layer)?;
+            } else {
+                writeln!(f, "{path}: {}", origin.layer)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        MikrotikDevice, mikrotik_model,
+        model::{IpPoolCfg, SystemIdentityCfg},
+        resource::Creatable,
+    };
+    use serde::Deserialize;
+
+    mikrotik_model!(
+        name = ProfileData,
+        fields(
+            identity(single = "system/identity"),
+            address(by_id(path = "ip/address", keys()))
+        )
+    );
+
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Target {
+        identity: SystemIdentityCfg,
+        pool: Vec<IpPoolCfg>,
+    }
+
+    impl LayeredTarget for Target {
+        const COLLECTIONS: &'static [Collection] = &[
+            Collection {
+                field: "identity",
+                resource: b"system/identity",
+                keys: None,
+            },
+            Collection {
+                field: "pool",
+                resource: b"ip/pool",
+                keys: Some(&["name"]),
+            },
+        ];
+    }
+
+    #[test]
+    fn test_merge_layers() {
+        let base = Layer::parse(
+            "base",
+            "identity: {name: router}\npool:\n- {name: mgmt, ranges: 10.0.0.10-10.0.0.20, next-pool: spare, comment: management}\n- {name: guest, ranges: 10.0.30.10-10.0.30.200}\n",
+        )
+        .unwrap();
+        let site = Layer::parse(
+            "site",
+            "pool:\n- name: mgmt\n  ranges: 10.1.0.10-10.1.0.20\n  comment: !delete\n- !delete {name: guest}\n- {name: iot, ranges: 10.1.40.10-10.1.40.200}\n",
+        )
+        .unwrap();
+        let device = Layer::parse("device", "identity: {name: sw1}\n").unwrap();
+        let (target, provenance) = merge::<Target>(&[base, site, device]).unwrap();
+
+        assert_eq!(target.identity.name.0.as_ref(), b"sw1");
+        let pools = target
+            .pool
+            .iter()
+            .map(|pool| {
+                (
+                    pool.name.0.as_ref(),
+                    pool.next_pool.is_some(),
+                    pool.comment.is_some(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pools,
+            [(&b"mgmt"[..], true, false), (&b"iot"[..], false, false)]
+        );
+
+        let origin = |path: &str| {
+            provenance
+                .origin(path)
+                .map(|o| (o.layer.as_str(), o.deleted))
+        };
+        assert_eq!(origin("identity.name"), Some(("device", false)));
+        assert_eq!(origin("pool[name=mgmt].next-pool"), Some(("base", false)));
+        assert_eq!(origin("pool[name=mgmt].ranges"), Some(("site", false)));
+        assert_eq!(origin("pool[name=mgmt].comment"), Some(("site", true)));
+        assert_eq!(origin("pool[name=guest]"), Some(("site", true)));
+        assert_eq!(origin("pool[name=guest].ranges"), None);
+
+        let mutation = provenance.annotate(target.pool[0].calculate_create());
+        assert_eq!(
+            mutation.provenance.as_deref(),
+            Some("set by base (next-pool), site (name, ranges)")
+        );
+
+        let unknown = Layer::parse("device", "pools: []\n").unwrap();
+        assert!(matches!(
+            merge::<Target>(&[unknown]),
+            Err(ProfileError::InvalidLayer { .. })
+        ));
+    }
+
+    #[test]
+    fn test_delete_single_resource() {
+        let base = Layer::parse("base", "identity: {name: router}\n").unwrap();
+        let device = Layer::parse("device", "identity: !delete\n").unwrap();
+        let error = merge::<Target>(&[base, device]).unwrap_err();
+        assert!(matches!(error, ProfileError::InvalidLayer { .. }));
+        assert_eq!(
+            error.to_string(),
+            "layer device: identity: single resources cannot be deleted"
+        );
+    }
+
+    #[test]
+    fn test_merge_generated_target() {
+        let addresses = ProfileDataTarget::COLLECTIONS
+            .iter()
+            .filter(|collection| collection.field == "address")
+            .collect::<Vec<_>>();
+        assert_eq!(addresses.len(), 1);
+        assert_eq!(addresses[0].keys, Some(&[][..]));
+
+        let base = Layer::parse(
+            "base",
+            "identity: {name: router}\naddress:\n- {address: 10.0.0.1/24, interface: ether1}\n",
+        )
+        .unwrap();
+        let site = Layer::parse(
+            "site",
+            "address:\n- {address: 10.0.1.1/24, interface: ether2}\n",
+        )
+        .unwrap();
+        let (target, _) = merge::<ProfileDataTarget>(&[base, site]).unwrap();
+        assert_eq!(target.address.len(), 2);
+    }
+}
@@ -2,20 +2,31 @@ use crate::value::Id;
 use crate::{
     MikrotikDevice,
     ascii::AsciiString,
+    generator::Generator,
     hwconfig::DeviceType,
-    model::{ReferenceType, Resource, ResourceRef, ResourceType},
-    value::{KeyValuePair, RosValue},
+    model::{
+        self, ReferenceType, Resource, ResourceRef, ResourceType, SystemSchedulerByName,
+        SystemSchedulerCfg, SystemSchedulerPolicy,
+    },
+    value::{KeyValuePair, ParseRosValueResult, RosValue},
 };
-use encoding_rs::mem::decode_latin1;
+use encoding_rs::mem::{decode_latin1, encode_latin1_lossy};
 use itertools::{EitherOrBoth, Itertools};
-use log::{debug, info};
+use log::{debug, info, warn};
 use mikrotik_api::prelude::{CommandBuilder, ParsedMessage, TrapCategory, TrapResult};
+use serde::{
+    Deserialize, Deserializer, Serializer,
+    de::{self, MapAccess, Visitor},
+    ser::SerializeMap,
+};
 use std::{
     borrow::Cow,
-    collections::HashSet,
+    collections::{BTreeSet, HashMap, HashSet},
     fmt::{Debug, Display, Formatter},
     hash::Hash,
+    marker::PhantomData,
     sync::Arc,
+    time::Duration,
 };
 use thiserror::Error;
 use tokio_stream::{FromStream, Stream, StreamExt, empty};
@@ -100,6 +111,131 @@ pub enum AppendFieldResult {
     UnknownField,
 }
 
+/// Writes the fields under their RouterOS names, used by the generated `serde` implementations
+pub trait SerializeRosFields {
+    /// RouterOS names of all fields, reported when deserializing an unknown one
+    fn field_names() -> &'static [&'static str];
+    fn serialize_fields<M: SerializeMap>(&self, map: &mut M) -> Result<(), M::Error>;
+}
+
+/// Serializes `resource` as map from RouterOS field names to the textual RouterOS values
+pub fn serialize_ros_fields<R: SerializeRosFields, S: Serializer>(
+    resource: &R,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(None)?;
+    resource.serialize_fields(&mut map)?;
+    map.end()
+}
+
+pub fn serialize_ros_field<M: SerializeMap, V: RosValue>(
+    map: &mut M,
+    key: &'static [u8],
+    value: &V,
+) -> Result<(), M::Error> {
+    map.serialize_entry(
+        &decode_latin1(key),
+        &decode_latin1(&value.encode_readable()),
+    )
+}
+
+/// Reads a map written by [`serialize_ros_fields`] through the builder `B`. Numbers and
+/// booleans are accepted in place of their textual form, `null` values are skipped.
+pub fn deserialize_ros_fields<'de, R, B, D>(deserializer: D) -> Result<R, D::Error>
+where
+    R: DeserializeRosResource + SerializeRosFields,
+    B: DeserializeRosBuilder<R, Context = ()>,
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_map(RosFieldsVisitor::<R, B>(PhantomData))
+}
+
+struct RosFieldsVisitor<R, B>(PhantomData<(R, B)>);
+
+impl<'de, R, B> Visitor<'de> for RosFieldsVisitor<R, B>
+where
+    R: DeserializeRosResource + SerializeRosFields,
+    B: DeserializeRosBuilder<R, Context = ()>,
+{
+    type Value = R;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a map of RouterOS fields")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<R, A::Error> {
+        let mut builder = B::init(&());
+        while let Some((key, RosText(value))) = map.next_entry::<String, RosText>()? {
+            let Some(value) = value else {
+                continue;
+            };
+            match builder.append_field(
+                &encode_latin1_lossy(&key),
+                Some(&encode_latin1_lossy(&value)),
+            ) {
+                AppendFieldResult::Appended => {}
+                AppendFieldResult::InvalidValue(_) => {
+                    return Err(de::Error::custom(format_args!(
+                        "invalid value {value:?} for {key}"
+                    )));
+                }
+                AppendFieldResult::UnknownField => {
+                    return Err(de::Error::unknown_field(&key, R::field_names()));
+                }
+            }
+        }
+        builder.build().map_err(|missing| {
+            de::Error::custom(format_args!("missing field {}", decode_latin1(missing)))
+        })
+    }
+}
+
+/// Field value given as string or as scalar, `None` for `null`
+struct RosText(Option<String>);
+
+impl<'de> Deserialize<'de> for RosText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(RosTextVisitor)
+    }
+}
+
+struct RosTextVisitor;
+
+impl<'de> Visitor<'de> for RosTextVisitor {
+    type Value = RosText;
+
+    fn expecting(&self, formatter: &mut Formatter) -> std::fmt::Result {
+        formatter.write_str("a RouterOS value")
+    }
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<RosText, E> {
+        Ok(RosText(Some(v.to_string())))
+    }
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<RosText, E> {
+        Ok(RosText(Some(v.to_string())))
+    }
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<RosText, E> {
+        Ok(RosText(Some(v.to_string())))
+    }
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<RosText, E> {
+        Ok(RosText(Some(v.to_string())))
+    }
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<RosText, E> {
+        Ok(RosText(Some(v.to_owned())))
+    }
+    fn visit_string<E: de::Error>(self, v: String) -> Result<RosText, E> {
+        Ok(RosText(Some(v)))
+    }
+    fn visit_none<E: de::Error>(self) -> Result<RosText, E> {
+        Ok(RosText(None))
+    }
+    fn visit_unit<E: de::Error>(self) -> Result<RosText, E> {
+        Ok(RosText(None))
+    }
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<RosText, D::Error> {
+        RosText::deserialize(deserializer)
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Cannot parse result: {resource_type:?} {error}")]
@@ -117,6 +253,16 @@ pub enum Error {
     ErrorFetchingSingleItem,
     #[error("Cannot identify type {0}")]
     UnknownType(AsciiString),
+    #[error("Unexpected object id {} received", decode_latin1(&.0.encode_ros()))]
+    UnexpectedObjectId(Id),
+    #[error("No .id assigned to pending entry {}", decode_latin1(.0))]
+    UnresolvedPendingId(Box<[u8]>),
+    #[error("Expected exactly one entry on {path} with {key:?}, found {found}")]
+    KeyLookup {
+        path: AsciiString,
+        key: KeyValuePair<'static>,
+        found: usize,
+    },
 }
 
 #[derive(Debug, Eq, PartialEq, Hash)]
@@ -149,7 +295,10 @@ pub async fn stream_resource<R: DeserializeRosResource + RosResource>(
 ) -> impl Stream<Item = SentenceResult<R>> {
     //println!("Fetch: {}", decode_latin1(R::path()));
     device
-        .send_simple_command(&[b"/", R::path(), b"/print"], R::resource_type())
+        .send_simple_command(
+            &[b"/", R::path(), b"/print"],
+            ReplyContext::Resource(R::resource_type()),
+        )
         .await
         .map(|entry| entry.map(|r| R::unwrap_resource(r).expect("Unexpected result type")))
 }
@@ -176,7 +325,7 @@ pub async fn monitor_once_resource<R: DeserializeRosResource + RosResource>(
                         cb.attribute(b".id", id_list.as_slice())
                             .attribute(b"once", b"true")
                     },
-                    R::resource_type(),
+                    ReplyContext::Resource(R::resource_type()),
                 )
                 .await
                 .map(|entry| entry.map(|r| R::unwrap_resource(r).expect("Unexpected result type"))),
@@ -239,9 +388,27 @@ fn value_or_error<R: DeserializeRosResource>(entry: SentenceResult<R>) -> Result
         SentenceResult::Trap { category, message } => {
             Err(Error::Trap(TrapResponse { category, message }))
         }
+        SentenceResult::ObjectId { id } => Err(Error::UnexpectedObjectId(id)),
     }
 }
 
+async fn fetch_all_filtered<R: KeyedResource, T: FromStream<R>>(
+    device: &MikrotikDevice,
+    filter: impl FnOnce(CommandBuilder) -> CommandBuilder,
+) -> Result<T, Error> {
+    device
+        .send_command(
+            &[b"/", R::path(), b"/print"],
+            |cmd| filter(cmd.query_is_present(R::key_name())),
+            ReplyContext::Resource(R::resource_type()),
+        )
+        .await
+        .map(|entry| entry.map(|r| R::unwrap_resource(r).expect("Unexpected result type")))
+        .map(|entry| value_or_error(entry))
+        .collect::<Result<T, _>>()
+        .await
+}
+
 pub trait KeyedResource: DeserializeRosResource + RosResource {
     type Key: RosValue;
     type Value: DeserializeRosResource + RosResource;
@@ -251,6 +418,11 @@ pub trait KeyedResource: DeserializeRosResource + RosResource {
     fn filter(cmd: CommandBuilder) -> CommandBuilder {
         cmd
     }
+    /// `false` for entries created by the device itself (dynamic, builtin or default ones),
+    /// which cannot be removed or modified. The diff functions leave them alone.
+    fn is_user_managed(&self) -> bool {
+        true
+    }
     fn fetch_all<T: FromStream<Self> + Send>(
         device: &MikrotikDevice,
     ) -> impl std::future::Future<Output = Result<T, Error>>
@@ -258,21 +430,19 @@ pub trait KeyedResource: DeserializeRosResource + RosResource {
         <Self as KeyedResource>::Key: Sync,
         Self: Send,
     {
-        async {
-            device
-                .send_command(
-                    &[b"/", Self::path(), b"/print"],
-                    |cmd| Self::filter(cmd.query_is_present(Self::key_name())),
-                    Self::resource_type(),
-                )
-                .await
-                .map(|entry| {
-                    entry.map(|r| Self::unwrap_resource(r).expect("Unexpected result type"))
-                })
-                .map(|entry| value_or_error(entry))
-                .collect::<Result<T, _>>()
-                .await
-        }
+        fetch_all_filtered(device, Self::filter)
+    }
+    /// Same as [`KeyedResource::fetch_all`], but also fetches the entries which are not
+    /// [user managed](KeyedResource::is_user_managed). To be used with the
+    /// `*_including_unmanaged` diff functions.
+    fn fetch_all_including_unmanaged<T: FromStream<Self> + Send>(
+        device: &MikrotikDevice,
+    ) -> impl std::future::Future<Output = Result<T, Error>>
+    where
+        <Self as KeyedResource>::Key: Sync,
+        Self: Send,
+    {
+        fetch_all_filtered(device, |cmd| cmd)
     }
 
     fn fetch(
@@ -288,7 +458,7 @@ pub trait KeyedResource: DeserializeRosResource + RosResource {
                     .send_command(
                         &[b"/", Self::path(), b"/print"],
                         |cmd| Self::filter(cmd.query_equal(Self::key_name(), key.encode_ros())),
-                        Self::resource_type(),
+                        ReplyContext::Resource(Self::resource_type()),
                     )
                     .await
                     .map(|entry| {
@@ -308,11 +478,44 @@ pub trait CfgResource: DeserializeRosResource {
     #[allow(clippy::needless_lifetimes)]
     fn changed_values<'a, 'b>(&'a self, before: &'b Self)
     -> impl Iterator<Item = KeyValuePair<'a>>;
+    /// Names of the fields set in `before` but returned to their default here
+    fn unset_fields(&self, _before: &Self) -> impl Iterator<Item = &'static [u8]> {
+        std::iter::empty()
+    }
+    fn field_values(&self) -> impl Iterator<Item = KeyValuePair<'_>>;
+}
+/// Looks up the values of the given `fields` and `unset` fields in `before`, empty if not
+/// set there.
+pub fn previous_values<'b>(
+    fields: &[KeyValuePair],
+    unset: &[&'static [u8]],
+    before: &'b impl CfgResource,
+) -> Box<[KeyValuePair<'b>]> {
+    let before_values = before.field_values().collect::<Vec<_>>();
+    fields
+        .iter()
+        .map(|changed| changed.key)
+        .chain(unset.iter().copied())
+        .map(|key| {
+            before_values
+                .iter()
+                .find(|kv| kv.key == key)
+                .cloned()
+                .unwrap_or(KeyValuePair {
+                    key,
+                    value: Cow::Borrowed(b""),
+                })
+        })
+        .collect()
 }
 pub trait SetResource<Base: RosResource>: FieldUpdateHandler {
     #[allow(clippy::needless_lifetimes)]
     fn changed_values<'a, 'b>(&'a self, before: &'b Base)
     -> impl Iterator<Item = KeyValuePair<'a>>;
+    /// Names of the fields set in `before` but returned to their default here
+    fn unset_fields(&self, _before: &Base) -> impl Iterator<Item = &'static [u8]> {
+        std::iter::empty()
+    }
 }
 
 #[derive(Clone, PartialEq)]
@@ -320,6 +523,14 @@ pub struct ResourceMutation<'a> {
     pub resource: &'static [u8],
     pub operation: ResourceMutationOperation<'a>,
     pub fields: Box<[KeyValuePair<'a>]>,
+    /// Values of the changed `fields` and `unset` fields before the mutation, if known
+    pub previous: Box<[KeyValuePair<'a>]>,
+    /// Fields returned to their default value, sent as `!name` / rendered as `unset`
+    pub unset: Box<[&'static [u8]]>,
+    /// Why the mutation is made, e.g. `managed by intent: vlan-trunk`, rendered by an
+    /// [annotated](crate::generator::Generator::annotated) generator. Never filled in by the
+    /// diff functions, set it with [`ResourceMutation::with_provenance`].
+    pub provenance: Option<Cow<'static, str>>,
     pub depends: Box<[(ReferenceType, Cow<'a, [u8]>)]>,
     pub provides: Box<[(ReferenceType, Cow<'a, [u8]>)]>,
 }
@@ -327,10 +538,13 @@ impl Debug for ResourceMutation<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "ResourceMutation {{resource: {}, operation: {:?}, fields: {:?}, depends: [",
+            "ResourceMutation {{resource: {}, operation: {:?}, fields: {:?}, previous: {:?}, unset: [{}], provenance: {:?}, depends: [",
             decode_latin1(self.resource),
             self.operation,
-            self.fields
+            self.fields,
+            self.previous,
+            self.unset.iter().map(|name| decode_latin1(name)).join(", "),
+            self.provenance
         )?;
         for (idx, (reference, id)) in self.depends.iter().enumerate() {
             if idx > 0 {
@@ -378,6 +592,11 @@ impl<'a> ResourceMutation<'a> {
             .into_iter()
             .map(|kv| KeyValuePair::into_owned(kv))
             .collect();
+        let previous = self
+            .previous
+            .into_iter()
+            .map(KeyValuePair::into_owned)
+            .collect();
         let depends = self
             .depends
             .into_iter()
@@ -392,6 +611,9 @@ impl<'a> ResourceMutation<'a> {
             resource: self.resource,
             operation,
             fields,
+            previous,
+            unset: self.unset,
+            provenance: self.provenance,
             depends,
             provides,
         }
@@ -451,6 +673,193 @@ impl<'a> ResourceMutation<'a> {
         }
         Ok(sorted_mutations.into_boxed_slice())
     }
+    pub fn remove_by_id(resource: &'static [u8], id: Id) -> ResourceMutation<'static> {
+        ResourceMutation {
+            resource,
+            operation: ResourceMutationOperation::RemoveByKey(KeyValuePair {
+                key: b".id",
+                value: Cow::Owned(id.encode_ros().into_owned()),
+            }),
+            fields: Box::new([]),
+            previous: Box::new([]),
+            unset: Box::new([]),
+            provenance: None,
+            depends: Box::new([]),
+            provides: Box::new([]),
+        }
+    }
+    /// Marks the entry added by this mutation as `pending`, so following mutations can refer to
+    /// it by [`PendingId::key`] before its `.id` is known.
+    pub fn provides_pending(mut self, pending: PendingId) -> Self {
+        self.provides = self
+            .provides
+            .into_iter()
+            .chain(std::iter::once(pending.reference()))
+            .collect();
+        self
+    }
+    /// Declares that this mutation refers to the entry added as `pending`, either by its key or
+    /// by a field value.
+    pub fn depends_on_pending(mut self, pending: PendingId) -> Self {
+        self.depends = self
+            .depends
+            .into_iter()
+            .chain(std::iter::once(pending.reference()))
+            .collect();
+        self
+    }
+    /// Tags the mutation with the reason it is made. The diff functions leave the provenance
+    /// empty, callers set it on their results, e.g.
+    /// `generate_add_update_remove_by_key(..).map(|m| m.with_provenance("managed by intent: vlan-trunk"))`
+    pub fn with_provenance(mut self, provenance: impl Into<Cow<'static, str>>) -> Self {
+        self.provenance = Some(provenance.into());
+        self
+    }
+    fn pending_references(&self) -> impl Iterator<Item = &[u8]> {
+        self.depends
+            .iter()
+            .filter(|(ref_type, _)| *ref_type == ReferenceType::ObjectId)
+            .map(|(_, value)| value.as_ref())
+    }
+    /// Calculates the mutation reverting `self`, `current` is the entry as it is before `self`
+    /// is applied.
+    ///
+    /// Returns `None` if `current` is not of the mutated resource or if `self` is an
+    /// [`ResourceMutationOperation::Add`]: the added entry can only be addressed by the `.id`
+    /// assigned by the device (see [`ResourceMutation::remove_by_id`]). A
+    /// [`ResourceMutationOperation::Move`] cannot be reverted either, the original position
+    /// depends on the other moves.
+    pub fn inverse<'c, C: CfgResource + RosResource>(
+        &self,
+        current: &'c C,
+    ) -> Option<ResourceMutation<'c>> {
+        if self.resource != C::path() {
+            return None;
+        }
+        let depends = current
+            .consumes_reference()
+            .filter(|(_, value)| !value.is_empty())
+            .collect();
+        let provides = current
+            .provides_reference()
+            .filter(|(_, value)| !value.is_empty())
+            .collect();
+        let operation = match &self.operation {
+            ResourceMutationOperation::Add | ResourceMutationOperation::Move { .. } => return None,
+            ResourceMutationOperation::RemoveByKey(_) => {
+                return Some(ResourceMutation {
+                    resource: self.resource,
+                    operation: ResourceMutationOperation::Add,
+                    fields: current
+                        .field_values()
+                        .filter(|KeyValuePair { key: _, value }| !value.is_empty())
+                        .collect(),
+                    previous: Box::new([]),
+                    unset: Box::new([]),
+                    provenance: None,
+                    depends,
+                    provides,
+                });
+            }
+            ResourceMutationOperation::UpdateSingle => ResourceMutationOperation::UpdateSingle,
+            ResourceMutationOperation::UpdateByKey(key) => {
+                // the key itself could be changed by the update
+                let key = self
+                    .fields
+                    .iter()
+                    .find(|field| field.key == key.key)
+                    .unwrap_or(key);
+                ResourceMutationOperation::UpdateByKey(key.clone().into_owned())
+            }
+        };
+        // fields not set before are unset again, typed fields reject an empty value
+        let (fields, unset): (Vec<_>, Vec<_>) = previous_values(&self.fields, &self.unset, current)
+            .into_iter()
+            .partition(|field| !field.value.is_empty());
+        Some(ResourceMutation {
+            resource: self.resource,
+            operation,
+            fields: fields.into_boxed_slice(),
+            previous: self
+                .fields
+                .iter()
+                .cloned()
+                .map(KeyValuePair::into_owned)
+                .collect(),
+            unset: unset.into_iter().map(|field| field.key).collect(),
+            provenance: None,
+            depends,
+            provides,
+        })
+    }
+    /// Same as [`ResourceMutation::inverse`] but looks up the entry addressed by the key of
+    /// `self` in `current`.
+    ///
+    /// An [`ResourceMutationOperation::Add`] is reverted by removing the entry by its key if the
+    /// key is part of the added fields.
+    pub fn inverse_by_key<'c, C: KeyedResource + CfgResource>(
+        &self,
+        current: &'c [C],
+    ) -> Option<ResourceMutation<'c>> {
+        if self.resource != C::path() {
+            return None;
+        }
+        let key = match &self.operation {
+            ResourceMutationOperation::RemoveByKey(key)
+            | ResourceMutationOperation::UpdateByKey(key) => key,
+            ResourceMutationOperation::Add => {
+                return self
+                    .fields
+                    .iter()
+                    .find(|field| field.key == C::key_name())
+                    .map(|key| ResourceMutation {
+                        resource: self.resource,
+                        operation: ResourceMutationOperation::RemoveByKey(key.clone().into_owned()),
+                        fields: Box::new([]),
+                        previous: Box::new([]),
+                        unset: Box::new([]),
+                        provenance: None,
+                        depends: Box::new([]),
+                        provides: Box::new([]),
+                    });
+            }
+            ResourceMutationOperation::UpdateSingle | ResourceMutationOperation::Move { .. } => {
+                return None;
+            }
+        };
+        if key.key != C::key_name() {
+            return None;
+        }
+        current
+            .iter()
+            .find(|entry| entry.key_value().encode_ros() == key.value)
+            .and_then(|entry| self.inverse(entry))
+    }
+}
+
+/// Placeholder for the `.id` of an entry added by a mutation that is not applied yet.
+///
+/// The executor replaces it by the `.id` the device returns for the `add`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PendingId {
+    pub resource: &'static [u8],
+    pub index: u32,
+}
+
+impl PendingId {
+    fn encode(self) -> Cow<'static, [u8]> {
+        Cow::Owned(format!("pending:{}:{}", decode_latin1(self.resource), self.index).into_bytes())
+    }
+    /// `.id` key addressing the pending entry
+    pub fn key(self) -> KeyValuePair<'static> {
+        KeyValuePair {
+            key: b".id",
+            value: self.encode(),
+        }
+    }
+    pub fn reference(self) -> (ReferenceType, Cow<'static, [u8]>) {
+        (ReferenceType::ObjectId, self.encode())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -459,6 +868,12 @@ pub enum ResourceMutationOperation<'a> {
     RemoveByKey(KeyValuePair<'a>),
     UpdateSingle,
     UpdateByKey(KeyValuePair<'a>),
+    /// moves the entry found by `key` before the entry found by `destination`, to the end of
+    /// the list if there is no `destination`
+    Move {
+        key: KeyValuePair<'a>,
+        destination: Option<KeyValuePair<'a>>,
+    },
 }
 
 impl<'a> ResourceMutationOperation<'a> {
@@ -472,8 +887,537 @@ impl<'a> ResourceMutationOperation<'a> {
             ResourceMutationOperation::UpdateByKey(k) => {
                 ResourceMutationOperation::UpdateByKey(k.into_owned())
             }
+            ResourceMutationOperation::Move { key, destination } => {
+                ResourceMutationOperation::Move {
+                    key: key.into_owned(),
+                    destination: destination.map(KeyValuePair::into_owned),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MutationOutcome {
+    /// accepted by the device, `id` is the `.id` of the added or modified entry
+    Applied {
+        id: Option<Id>,
+    },
+    /// no fields to change, nothing sent
+    Unchanged,
+    Failed(Error),
+    /// not sent because a previous mutation failed
+    Skipped,
+}
+
+#[derive(Debug)]
+pub struct MutationResult<'m, 'a> {
+    pub mutation: &'m ResourceMutation<'a>,
+    pub outcome: MutationOutcome,
+}
+
+impl MutationResult<'_, '_> {
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self.outcome,
+            MutationOutcome::Applied { .. } | MutationOutcome::Unchanged
+        )
+    }
+}
+
+/// `.id`s the device assigned to entries added as [`PendingId`] within one run.
+#[derive(Debug, Default)]
+struct AssignedIds(HashMap<Box<[u8]>, Id>);
+
+impl AssignedIds {
+    fn record(&mut self, mutation: &ResourceMutation, outcome: &MutationOutcome) {
+        if let (ResourceMutationOperation::Add, MutationOutcome::Applied { id: Some(id) }) =
+            (&mutation.operation, outcome)
+        {
+            for (ref_type, value) in &mutation.provides {
+                if *ref_type == ReferenceType::ObjectId {
+                    self.0.insert(Box::from(value.as_ref()), *id);
+                }
+            }
+        }
+    }
+    /// Replaces all pending `.id`s the mutation depends on by the assigned ones.
+    fn resolve<'m, 'a>(
+        &self,
+        mutation: &'m ResourceMutation<'a>,
+    ) -> Result<Cow<'m, ResourceMutation<'a>>, Error> {
+        let mut resolved = Cow::Borrowed(mutation);
+        for pending in mutation.pending_references() {
+            let id = self
+                .0
+                .get(pending)
+                .ok_or_else(|| Error::UnresolvedPendingId(Box::from(pending)))?
+                .encode_ros();
+            let replace = |kv: &mut KeyValuePair<'a>| {
+                if kv.value.as_ref() == pending {
+                    kv.value = Cow::Owned(id.clone().into_owned());
+                }
+            };
+            let target = resolved.to_mut();
+            match &mut target.operation {
+                ResourceMutationOperation::RemoveByKey(key)
+                | ResourceMutationOperation::UpdateByKey(key) => replace(key),
+                ResourceMutationOperation::Move { key, destination } => {
+                    replace(key);
+                    destination.iter_mut().for_each(replace);
+                }
+                ResourceMutationOperation::Add | ResourceMutationOperation::UpdateSingle => {}
+            }
+            target.fields.iter_mut().for_each(replace);
+        }
+        Ok(resolved)
+    }
+    async fn apply(
+        &mut self,
+        device: &MikrotikDevice,
+        mutation: &ResourceMutation<'_>,
+    ) -> MutationOutcome {
+        let outcome = match self.resolve(mutation) {
+            Ok(resolved) => apply_mutation(device, &resolved).await,
+            Err(error) => MutationOutcome::Failed(error),
+        };
+        self.record(mutation, &outcome);
+        outcome
+    }
+}
+
+/// Sends the mutations (as sorted by [`ResourceMutation::sort_mutations`]) to the device.
+///
+/// Entries added as [`PendingId`] are addressed by the `.id` the device returned for the `add`
+/// in all following mutations.
+///
+/// Processing stops at the first failing mutation, all following mutations are reported as
+/// [`MutationOutcome::Skipped`].
+pub async fn apply_mutations<'m, 'a>(
+    device: &MikrotikDevice,
+    mutations: &[&'m ResourceMutation<'a>],
+) -> Box<[MutationResult<'m, 'a>]> {
+    let mut results = Vec::with_capacity(mutations.len());
+    let mut assigned_ids = AssignedIds::default();
+    let mut failed = false;
+    for mutation in mutations.iter().copied() {
+        let outcome = if failed {
+            MutationOutcome::Skipped
+        } else {
+            assigned_ids.apply(device, mutation).await
+        };
+        if let MutationOutcome::Failed(error) = &outcome {
+            info!(
+                "Failed to apply mutation on {}: {error}",
+                decode_latin1(mutation.resource)
+            );
+            failed = true;
+        }
+        results.push(MutationResult { mutation, outcome });
+    }
+    results.into_boxed_slice()
+}
+
+/// Provides the state to revert mutations to, implemented by the `*Current` structs
+/// generated by `mikrotik_model!`.
+pub trait RollbackSource {
+    fn inverse_mutation(&self, mutation: &ResourceMutation) -> Option<ResourceMutation<'_>>;
+}
+
+#[derive(Debug)]
+pub struct RollbackResult<'m, 'a, 'r> {
+    pub results: Box<[MutationResult<'m, 'a>]>,
+    /// Mutations sent to revert the applied changes after a failure, empty if all mutations
+    /// were applied.
+    pub rollback: Box<[(ResourceMutation<'r>, MutationOutcome)]>,
+}
+
+impl RollbackResult<'_, '_, '_> {
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(MutationResult::is_success)
+    }
+}
+
+/// Same as [`apply_mutations`], but reverts all already applied mutations in reverse order if
+/// one of them fails.
+///
+/// `current` has to be the state the mutations are calculated from.
+pub async fn apply_mutations_with_rollback<'m, 'a, 'r>(
+    device: &MikrotikDevice,
+    mutations: &[&'m ResourceMutation<'a>],
+    current: &'r impl RollbackSource,
+) -> RollbackResult<'m, 'a, 'r> {
+    let mut results = Vec::with_capacity(mutations.len());
+    let mut inverse_mutations = Vec::with_capacity(mutations.len());
+    let mut assigned_ids = AssignedIds::default();
+    let mut failed = false;
+    for mutation in mutations.iter().copied() {
+        if failed {
+            results.push(MutationResult {
+                mutation,
+                outcome: MutationOutcome::Skipped,
+            });
+            continue;
+        }
+        let inverse = current.inverse_mutation(mutation);
+        let outcome = assigned_ids.apply(device, mutation).await;
+        match (&outcome, &mutation.operation) {
+            (MutationOutcome::Applied { id: Some(id) }, ResourceMutationOperation::Add) => {
+                inverse_mutations.push(ResourceMutation::remove_by_id(mutation.resource, *id))
+            }
+            // changes on entries added in this run are reverted by removing them
+            (MutationOutcome::Applied { .. }, _)
+                if mutation.pending_references().next().is_some() => {}
+            (MutationOutcome::Applied { .. }, _) => {
+                if let Some(inverse) = inverse {
+                    inverse_mutations.push(inverse);
+                } else {
+                    warn!(
+                        "Cannot revert mutation on {}",
+                        decode_latin1(mutation.resource)
+                    );
+                }
+            }
+            (MutationOutcome::Failed(error), _) => {
+                info!(
+                    "Failed to apply mutation on {}: {error}",
+                    decode_latin1(mutation.resource)
+                );
+                failed = true;
+            }
+            (MutationOutcome::Unchanged | MutationOutcome::Skipped, _) => {}
+        }
+        results.push(MutationResult { mutation, outcome });
+    }
+    let mut rollback = Vec::new();
+    if failed {
+        for inverse in inverse_mutations.into_iter().rev() {
+            let outcome = apply_mutation(device, &inverse).await;
+            if let MutationOutcome::Failed(error) = &outcome {
+                warn!(
+                    "Failed to revert mutation on {}: {error}",
+                    decode_latin1(inverse.resource)
+                );
+            }
+            rollback.push((inverse, outcome));
+        }
+    }
+    RollbackResult {
+        results: results.into_boxed_slice(),
+        rollback: rollback.into_boxed_slice(),
+    }
+}
+
+const REVERT_SCHEDULER_NAME: &[u8] = b"mikrotik-model-revert";
+
+#[derive(Debug, Error)]
+pub enum CommitError<'m, 'a, 'r> {
+    #[error("Cannot install revert scheduler: {0}")]
+    Scheduler(Error),
+    #[error("Failed to apply mutations, applied changes are reverted")]
+    Reverted(RollbackResult<'m, 'a, 'r>),
+}
+
+/// Mutations applied by [`apply_mutations_confirmed`], reverted by the device unless confirmed
+/// in time.
+#[derive(Debug)]
+pub struct PendingCommit<'m, 'a> {
+    pub results: Box<[MutationResult<'m, 'a>]>,
+}
+
+impl PendingCommit<'_, '_> {
+    /// Removes the scheduler reverting the changes.
+    pub async fn confirm(self, device: &MikrotikDevice) -> Result<(), Error> {
+        match apply_mutation(device, &remove_revert_scheduler()).await {
+            MutationOutcome::Failed(error) => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Applies the mutations like `commit confirmed`: before anything is changed, a
+/// `/system/scheduler` entry is installed on the device running the revert script after
+/// `timeout`. The changes stay only if [`PendingCommit::confirm`] is called in time.
+///
+/// Added entries without a key (like `.id` addressed firewall rules) are only covered by the
+/// revert script after all mutations are applied, as their `.id` is assigned by the device.
+pub async fn apply_mutations_confirmed<'m, 'a, 'r>(
+    device: &MikrotikDevice,
+    mutations: &[&'m ResourceMutation<'a>],
+    current: &'r impl RollbackSource,
+    timeout: Duration,
+) -> Result<PendingCommit<'m, 'a>, CommitError<'m, 'a, 'r>> {
+    let inverse_mutations = mutations
+        .iter()
+        .map(|mutation| current.inverse_mutation(mutation))
+        .collect::<Vec<_>>();
+    let scheduler = SystemSchedulerByName(SystemSchedulerCfg {
+        comment: None,
+        disabled: false,
+        interval: timeout,
+        name: REVERT_SCHEDULER_NAME.into(),
+        on_event: revert_script(inverse_mutations.iter().rev().flatten()),
+        policy: BTreeSet::from([
+            SystemSchedulerPolicy::Read,
+            SystemSchedulerPolicy::Write,
+            SystemSchedulerPolicy::Policy,
+            SystemSchedulerPolicy::Test,
+            SystemSchedulerPolicy::Sensitive,
+        ]),
+        start_date: None,
+        start_time: None,
+    });
+    if let MutationOutcome::Failed(error) =
+        apply_mutation(device, &scheduler.calculate_create()).await
+    {
+        return Err(CommitError::Scheduler(error));
+    }
+
+    let result = apply_mutations_with_rollback(device, mutations, current).await;
+    if !result.is_success() {
+        if let MutationOutcome::Failed(error) =
+            apply_mutation(device, &remove_revert_scheduler()).await
+        {
+            warn!("Cannot remove revert scheduler: {error}");
+        }
+        return Err(CommitError::Reverted(result));
+    }
+
+    let applied_inverse_mutations = result
+        .results
+        .iter()
+        .zip(inverse_mutations)
+        .filter_map(
+            |(result, inverse)| match (&result.outcome, &result.mutation.operation) {
+                (MutationOutcome::Applied { id: Some(id) }, ResourceMutationOperation::Add) => {
+                    Some(ResourceMutation::remove_by_id(
+                        result.mutation.resource,
+                        *id,
+                    ))
+                }
+                (MutationOutcome::Applied { .. }, _) => inverse,
+                _ => None,
+            },
+        )
+        .collect::<Vec<_>>();
+    let mut updated_scheduler = scheduler.clone();
+    updated_scheduler.0.on_event = revert_script(applied_inverse_mutations.iter().rev());
+    if let MutationOutcome::Failed(error) =
+        apply_mutation(device, &updated_scheduler.calculate_update(&scheduler)).await
+    {
+        warn!("Cannot update revert script, added entries will not be removed: {error}");
+    }
+    Ok(PendingCommit {
+        results: result.results,
+    })
+}
+
+/// The scheduler is removed before anything is reverted, so a failing inverse command does
+/// not run the partial rollback again after the next `interval`.
+fn revert_script<'m, 'a: 'm>(
+    inverse_mutations: impl Iterator<Item = &'m ResourceMutation<'a>>,
+) -> AsciiString {
+    let mut script = String::new();
+    let mut generator = Generator::new(&mut script);
+    generator
+        .append_mutation(&remove_revert_scheduler())
+        .expect("Cannot write to string");
+    for mutation in inverse_mutations {
+        generator
+            .append_mutation(mutation)
+            .expect("Cannot write to string");
+    }
+    script.into()
+}
+
+fn remove_revert_scheduler() -> ResourceMutation<'static> {
+    ResourceMutation {
+        resource: SystemSchedulerCfg::path(),
+        operation: ResourceMutationOperation::RemoveByKey(KeyValuePair {
+            key: b"name",
+            value: Cow::Borrowed(REVERT_SCHEDULER_NAME),
+        }),
+        fields: Box::new([]),
+        previous: Box::new([]),
+        unset: Box::new([]),
+        provenance: None,
+        depends: Box::new([]),
+        provides: Box::new([]),
+    }
+}
+
+pub async fn apply_mutation(
+    device: &MikrotikDevice,
+    mutation: &ResourceMutation<'_>,
+) -> MutationOutcome {
+    if mutation.fields.is_empty()
+        && mutation.unset.is_empty()
+        && !matches!(
+            mutation.operation,
+            ResourceMutationOperation::RemoveByKey(_) | ResourceMutationOperation::Move { .. }
+        )
+    {
+        return MutationOutcome::Unchanged;
+    }
+    match send_mutation(device, mutation).await {
+        Ok(id) => MutationOutcome::Applied { id },
+        Err(error) => MutationOutcome::Failed(error),
+    }
+}
+
+async fn send_mutation(
+    device: &MikrotikDevice,
+    mutation: &ResourceMutation<'_>,
+) -> Result<Option<Id>, Error> {
+    let path = mutation.resource;
+    let context = enum_iterator::all::<ResourceType>()
+        .find(|t| t.path() == path)
+        .ok_or_else(|| Error::UnknownType(AsciiString::from(path)))?;
+    let fields = &mutation.fields;
+    let unset = &mutation.unset;
+    Ok(match &mutation.operation {
+        ResourceMutationOperation::Add => {
+            let stream = device
+                .send_command(
+                    &[b"/", path, b"/add"],
+                    |cmd| append_attributes(cmd, fields),
+                    ReplyContext::Add,
+                )
+                .await;
+            collect_object_ids(stream, context).await?.pop()
+        }
+        ResourceMutationOperation::UpdateSingle => {
+            let stream = device
+                .send_command(
+                    &[b"/", path, b"/set"],
+                    |cmd| append_unset(append_attributes(cmd, fields), unset),
+                    ReplyContext::Resource(context),
+                )
+                .await;
+            collect_object_ids(stream, context).await?;
+            None
+        }
+        ResourceMutationOperation::UpdateByKey(key) => {
+            let id = lookup_id(device, path, key, context).await?;
+            let stream = device
+                .send_command(
+                    &[b"/", path, b"/set"],
+                    |cmd| {
+                        append_unset(
+                            append_attributes(cmd.attribute(b".id", id.encode_ros()), fields),
+                            unset,
+                        )
+                    },
+                    ReplyContext::Resource(context),
+                )
+                .await;
+            collect_object_ids(stream, context).await?;
+            Some(id)
+        }
+        ResourceMutationOperation::RemoveByKey(key) => {
+            let id = lookup_id(device, path, key, context).await?;
+            let stream = device
+                .send_command(
+                    &[b"/", path, b"/remove"],
+                    |cmd| cmd.attribute(b".id", id.encode_ros()),
+                    ReplyContext::Resource(context),
+                )
+                .await;
+            collect_object_ids(stream, context).await?;
+            Some(id)
+        }
+        ResourceMutationOperation::Move { key, destination } => {
+            let id = lookup_id(device, path, key, context).await?;
+            let destination = match destination {
+                Some(destination) => Some(lookup_id(device, path, destination, context).await?),
+                None => None,
+            };
+            let stream = device
+                .send_command(
+                    &[b"/", path, b"/move"],
+                    |cmd| {
+                        let cmd = cmd.attribute(b"numbers", id.encode_ros());
+                        match destination {
+                            Some(destination) => {
+                                cmd.attribute(b"destination", destination.encode_ros())
+                            }
+                            None => cmd,
+                        }
+                    },
+                    ReplyContext::Resource(context),
+                )
+                .await;
+            collect_object_ids(stream, context).await?;
+            Some(id)
+        }
+    })
+}
+
+fn append_attributes(cmd: CommandBuilder, fields: &[KeyValuePair]) -> CommandBuilder {
+    fields
+        .iter()
+        .fold(cmd, |cmd, kv| cmd.attribute(kv.key, kv.value.as_ref()))
+}
+
+/// Fields are returned to their default by sending them as `!name`
+fn append_unset(cmd: CommandBuilder, unset: &[&'static [u8]]) -> CommandBuilder {
+    unset
+        .iter()
+        .fold(cmd, |cmd, name| cmd.attribute(&[b"!", *name].concat(), b""))
+}
+
+async fn lookup_id(
+    device: &MikrotikDevice,
+    path: &'static [u8],
+    key: &KeyValuePair<'_>,
+    context: ResourceType,
+) -> Result<Id, Error> {
+    if key.key == b".id"
+        && let ParseRosValueResult::Value(id) = Id::parse_ros(&key.value)
+    {
+        return Ok(id);
+    }
+    let stream = device
+        .send_command(
+            &[b"/", path, b"/print"],
+            |cmd| {
+                cmd.attribute(b".proplist", b".id")
+                    .query_equal(key.key, key.value.as_ref())
+            },
+            ReplyContext::ObjectIds,
+        )
+        .await;
+    match collect_object_ids(stream, context).await?.as_slice() {
+        [id] => Ok(*id),
+        found => Err(Error::KeyLookup {
+            path: AsciiString::from(path),
+            key: key.clone().into_owned(),
+            found: found.len(),
+        }),
+    }
+}
+
+async fn collect_object_ids(
+    mut stream: impl Stream<Item = SentenceResult<Resource>> + Unpin,
+    context: ResourceType,
+) -> Result<Vec<Id>, Error> {
+    let mut ids = Vec::new();
+    while let Some(entry) = stream.next().await {
+        match entry {
+            SentenceResult::ObjectId { id } => ids.push(id),
+            SentenceResult::Row { .. } => {}
+            SentenceResult::Error { errors, .. } => {
+                return Err(Error::ResourceAccess {
+                    error: errors.first().expect("Error without error").clone(),
+                    resource_type: context,
+                });
+            }
+            SentenceResult::Trap { category, message } => {
+                return Err(Error::Trap(TrapResponse { category, message }));
+            }
         }
     }
+    Ok(ids)
 }
 
 pub trait Updatable<From: RosResource>: DeserializeRosResource {
@@ -531,6 +1475,19 @@ pub enum SentenceResult<R> {
         category: Option<TrapCategory>,
         message: Box<[u8]>,
     },
+    /// `.id` returned in [`ReplyContext::Add`] or [`ReplyContext::ObjectIds`]
+    ObjectId { id: Id },
+}
+
+/// How the replies to a command are parsed into [`SentenceResult<Resource>`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyContext {
+    /// entries of the given type
+    Resource(ResourceType),
+    /// the `ret` of an `add`, the `.id` of the new entry
+    Add,
+    /// only the `.id`s of a print restricted to `.proplist=.id`
+    ObjectIds,
 }
 
 impl<R> SentenceResult<R> {
@@ -546,15 +1503,44 @@ impl<R> SentenceResult<R> {
             SentenceResult::Trap { category, message } => {
                 SentenceResult::Trap { category, message }
             }
+            SentenceResult::ObjectId { id } => SentenceResult::ObjectId { id },
         }
     }
 }
 
+/// Reads the `.id` in `field_name` of a sentence
+fn parse_object_id(
+    sentence: &[(&[u8], Option<&[u8]>)],
+    field_name: &'static [u8],
+) -> SentenceResult<Resource> {
+    let value = sentence
+        .iter()
+        .find(|(key, _)| *key == field_name)
+        .and_then(|(_, value)| *value);
+    let error = match value.map(Id::parse_ros) {
+        Some(ParseRosValueResult::Value(id)) => return SentenceResult::ObjectId { id },
+        Some(_) => ResourceAccessError::InvalidValueError {
+            field_name,
+            value: value.map(Box::from).unwrap_or_default(),
+        },
+        None => ResourceAccessError::MissingFieldError { field_name },
+    };
+    SentenceResult::Error {
+        errors: Arc::new(Box::new([error])),
+        warnings: Arc::default(),
+    }
+}
+
 impl ParsedMessage for SentenceResult<Resource> {
-    type Context = ResourceType;
+    type Context = ReplyContext;
 
     fn parse_message(sentence: &[(&[u8], Option<&[u8]>)], context: &Self::Context) -> Self {
-        let mut builder = context.create_builder();
+        let resource_type = match context {
+            ReplyContext::Resource(resource_type) => resource_type,
+            ReplyContext::Add => return parse_object_id(sentence, b"ret"),
+            ReplyContext::ObjectIds => return parse_object_id(sentence, b".id"),
+        };
+        let mut builder = resource_type.create_builder();
         let mut errors = Vec::new();
         let mut warnings = Vec::new();
         for (key, value) in sentence {
@@ -780,6 +1766,9 @@ where
                     resource: Current::path(),
                     operation: ResourceMutationOperation::RemoveByKey(KeyValuePair { key, value }),
                     fields: Box::new([]),
+                    previous: Box::new([]),
+                    unset: Box::new([]),
+                    provenance: None,
                     depends: Default::default(),
                     provides: Default::default(),
                 }
@@ -811,6 +1800,7 @@ where
         .changed_values(current)
         //.map(|e| e.into_owned())
         .collect::<Box<[_]>>();
+    let unset = target.unset_fields(current).collect::<Box<[_]>>();
     let provides = target
         .provides_reference()
         //.map(|(k, v)| (k, Cow::Owned(v.into_owned())))
@@ -823,7 +1813,10 @@ where
     ResourceMutation {
         resource: Resource::path(),
         operation: ResourceMutationOperation::UpdateSingle,
+        previous: previous_values(&fields, &unset, current),
         fields,
+        unset,
+        provenance: None,
         depends,
         provides,
     }
@@ -843,30 +1836,244 @@ where
     IntoTarget: Into<Cow<'t, Target>>,
     <TargetIter as IntoIterator>::IntoIter: 'r,
 {
-    let current_iter = current.iter();
-    let target_iter = target.into_iter();
-    current_iter
-        .zip_longest(target_iter)
-        .map(|entry| match entry {
-            EitherOrBoth::Both(current, target) => match target.into() {
-                Cow::Borrowed(t) => t.calculate_update(current),
-                Cow::Owned(t) => t.calculate_update(current).into_owned(),
-            },
-            EitherOrBoth::Left(current) => ResourceMutation {
-                resource: Current::path(),
-                operation: ResourceMutationOperation::RemoveByKey(KeyValuePair {
-                    key: Current::key_name(),
-                    value: current.key_value().encode_ros(),
-                }),
-                fields: Box::new([]),
-                depends: Box::new([]),
-                provides: Box::new([]),
-            },
-            EitherOrBoth::Right(target) => match target.into() {
-                Cow::Borrowed(t) => t.calculate_create(),
-                Cow::Owned(t) => t.calculate_create().into_owned(),
+    add_update_remove_by_id(
+        current.iter().filter(|entry| entry.is_user_managed()),
+        target,
+    )
+}
+/// Same as [`generate_add_update_remove_by_id`], but also updates or removes entries which are
+/// not [user managed](KeyedResource::is_user_managed).
+pub fn generate_add_update_remove_by_id_including_unmanaged<
+    'c,
+    't,
+    'r,
+    Target,
+    Current,
+    TargetIter,
+    IntoTarget,
+>(
+    current: &'c [Current],
+    target: TargetIter,
+) -> impl Iterator<Item = ResourceMutation<'r>> + 'r
+where
+    'c: 'r,
+    't: 'r,
+    Current: KeyedResource,
+    <Current as KeyedResource>::Value: 'static,
+    Target: CfgResource + Creatable + Updatable<Current> + Clone + 't,
+    TargetIter: IntoIterator<Item = IntoTarget>,
+    IntoTarget: Into<Cow<'t, Target>>,
+    <TargetIter as IntoIterator>::IntoIter: 'r,
+{
+    add_update_remove_by_id(current.iter(), target)
+}
+fn add_update_remove_by_id<'c, 't, 'r, Target, Current, TargetIter, IntoTarget>(
+    current: impl Iterator<Item = &'c Current> + 'r,
+    target: TargetIter,
+) -> impl Iterator<Item = ResourceMutation<'r>> + 'r
+where
+    'c: 'r,
+    't: 'r,
+    Current: KeyedResource + 'c,
+    <Current as KeyedResource>::Value: 'static,
+    Target: CfgResource + Creatable + Updatable<Current> + Clone + 't,
+    TargetIter: IntoIterator<Item = IntoTarget>,
+    IntoTarget: Into<Cow<'t, Target>>,
+    <TargetIter as IntoIterator>::IntoIter: 'r,
+{
+    let target_iter = target.into_iter().enumerate();
+    current.zip_longest(target_iter).map(|entry| match entry {
+        EitherOrBoth::Both(current, (_, target)) => match target.into() {
+            Cow::Borrowed(t) => t.calculate_update(current),
+            Cow::Owned(t) => t.calculate_update(current).into_owned(),
+        },
+        EitherOrBoth::Left(current) => ResourceMutation {
+            resource: Current::path(),
+            operation: ResourceMutationOperation::RemoveByKey(KeyValuePair {
+                key: Current::key_name(),
+                value: current.key_value().encode_ros(),
+            }),
+            fields: Box::new([]),
+            previous: Box::new([]),
+            unset: Box::new([]),
+            provenance: None,
+            depends: Box::new([]),
+            provides: Box::new([]),
+        },
+        // the added entry is only known by the `.id` the device assigns
+        EitherOrBoth::Right((idx, target)) => match target.into() {
+            Cow::Borrowed(t) => t.calculate_create(),
+            Cow::Owned(t) => t.calculate_create().into_owned(),
+        }
+        .provides_pending(PendingId {
+            resource: Current::path(),
+            index: idx as u32,
+        }),
+    })
+}
+
+/// Same as [`generate_add_update_remove_by_id`] for lists where the order matters, like
+/// firewall rules.
+///
+/// Current entries equal to a target entry are kept, the remaining ones are updated in place
+/// or removed. Afterwards all entries not part of the longest sequence already in target order
+/// are moved before their next kept successor. Missing entries are added last with
+/// `place-before` the next existing entry, or appended if there is none. Entries which are not
+/// [user managed](KeyedResource::is_user_managed) are neither moved nor removed.
+pub fn generate_ordered_by_id<'c, 't, 'r, Target, Current, TargetIter, IntoTarget>(
+    current: &'c [Current],
+    target: TargetIter,
+) -> impl Iterator<Item = ResourceMutation<'r>> + 'r
+where
+    'c: 'r,
+    't: 'r,
+    Current: KeyedResource,
+    <Current as KeyedResource>::Value: 'static,
+    Target: CfgResource + Creatable + Updatable<Current> + Clone + 't,
+    TargetIter: IntoIterator<Item = IntoTarget>,
+    IntoTarget: Into<Cow<'t, Target>>,
+{
+    let current = current
+        .iter()
+        .filter(|entry| entry.is_user_managed())
+        .collect::<Vec<_>>();
+    let targets = target.into_iter().map(Into::into).collect::<Vec<_>>();
+    let mut assigned_current = vec![None; targets.len()];
+    let mut used_current = vec![false; current.len()];
+    for (target, assigned) in targets.iter().zip(assigned_current.iter_mut()) {
+        if let Some(idx) = (0..current.len()).find(|&idx| {
+            if used_current[idx] {
+                return false;
+            }
+            let update = target.calculate_update(current[idx]);
+            update.fields.is_empty() && update.unset.is_empty()
+        }) {
+            used_current[idx] = true;
+            *assigned = Some(idx);
+        }
+    }
+    let mut free_current = (0..current.len()).filter(|&idx| !used_current[idx]);
+    let mut updated = Vec::new();
+    for (target_idx, assigned) in assigned_current.iter_mut().enumerate() {
+        if assigned.is_none() {
+            *assigned = free_current.next();
+            if let Some(current_idx) = *assigned {
+                updated.push((target_idx, current_idx));
+            }
+        }
+    }
+    let removed = free_current.collect::<Vec<_>>();
+
+    let pending_id = |idx: usize| PendingId {
+        resource: Current::path(),
+        index: idx as u32,
+    };
+    let current_key = |current_idx: usize| KeyValuePair {
+        key: Current::key_name(),
+        value: Cow::Owned(current[current_idx].key_value().encode_ros().into_owned()),
+    };
+    // next entry after `idx` in target order which exists already
+    let existing_successor =
+        |idx: usize| assigned_current[idx + 1..].iter().flatten().next().copied();
+    let kept = longest_increasing_subsequence(&assigned_current);
+
+    let mut mutations = Vec::with_capacity(targets.len() + removed.len());
+    for idx in removed {
+        mutations.push(ResourceMutation {
+            resource: Current::path(),
+            operation: ResourceMutationOperation::RemoveByKey(KeyValuePair {
+                key: Current::key_name(),
+                value: current[idx].key_value().encode_ros(),
+            }),
+            fields: Box::new([]),
+            previous: Box::new([]),
+            unset: Box::new([]),
+            provenance: None,
+            depends: Box::new([]),
+            provides: Box::new([]),
+        });
+    }
+    for (target_idx, current_idx) in updated {
+        mutations.push(
+            targets[target_idx]
+                .calculate_update(current[current_idx])
+                .into_owned(),
+        );
+    }
+    // moving from the end keeps the successor of every moved entry in place
+    for idx in (0..targets.len()).rev() {
+        let Some(current_idx) = assigned_current[idx] else {
+            continue;
+        };
+        if kept[idx] {
+            continue;
+        }
+        mutations.push(ResourceMutation {
+            resource: Current::path(),
+            operation: ResourceMutationOperation::Move {
+                key: current_key(current_idx),
+                destination: existing_successor(idx).map(current_key),
             },
-        })
+            fields: Box::new([]),
+            previous: Box::new([]),
+            unset: Box::new([]),
+            provenance: None,
+            depends: Box::new([]),
+            provides: Box::new([]),
+        });
+    }
+    // added entries wait for the previous one, so entries placed before the same successor
+    // keep their order
+    let mut previous_added = None;
+    for (idx, target) in targets.iter().enumerate() {
+        if assigned_current[idx].is_some() {
+            continue;
+        }
+        let mut add = target.calculate_create().into_owned();
+        if let Some(successor) = existing_successor(idx) {
+            add.fields = add
+                .fields
+                .into_iter()
+                .chain(std::iter::once(KeyValuePair {
+                    key: b"place-before",
+                    value: current_key(successor).value,
+                }))
+                .collect();
+        }
+        if let Some(previous) = previous_added {
+            add = add.depends_on_pending(previous);
+        }
+        mutations.push(add.provides_pending(pending_id(idx)));
+        previous_added = Some(pending_id(idx));
+    }
+    mutations.into_iter()
+}
+
+/// Marks the entries forming the longest strictly increasing sequence, `None` is never part
+/// of it.
+fn longest_increasing_subsequence(values: &[Option<usize>]) -> Vec<bool> {
+    // tails[len] is the index of the smallest last value of an increasing sequence of len+1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut predecessors = vec![None; values.len()];
+    for (idx, value) in values.iter().enumerate() {
+        let Some(value) = value else {
+            continue;
+        };
+        let len = tails.partition_point(|&tail| values[tail] < Some(*value));
+        predecessors[idx] = len.checked_sub(1).map(|len| tails[len]);
+        if len == tails.len() {
+            tails.push(idx);
+        } else {
+            tails[len] = idx;
+        }
+    }
+    let mut kept = vec![false; values.len()];
+    let mut next = tails.last().copied();
+    while let Some(idx) = next {
+        kept[idx] = true;
+        next = predecessors[idx];
+    }
+    kept
 }
 
 pub fn generate_add_update_remove_by_key<'c, 't, 'r, Target, Current>(
@@ -883,6 +2090,50 @@ where
 {
     UpdatePairing::match_updates_by_key(current, target).generate_remove_update_add()
 }
+/// Same as [`generate_add_update_remove_by_key`], but also updates or removes entries which
+/// are not [user managed](KeyedResource::is_user_managed).
+pub fn generate_add_update_remove_by_key_including_unmanaged<'c, 't, 'r, Target, Current>(
+    current: &'c [Current],
+    target: impl IntoIterator<Item = impl Into<Cow<'t, Target>>>,
+) -> impl Iterator<Item = ResourceMutation<'r>> + 'r
+where
+    'c: 'r,
+    't: 'r,
+    Current: KeyedResource,
+    <Current as KeyedResource>::Value: 'static,
+    Target: KeyedResource + CfgResource + Creatable + Updatable<Current> + Clone + 't,
+    <Target as KeyedResource>::Key: PartialEq<<Current as KeyedResource>::Key>,
+{
+    UpdatePairing::match_updates_by_key_including_unmanaged(current, target)
+        .generate_remove_update_add()
+}
+/// Same as [`generate_add_update_remove_by_key`], but entries with a changed key are renamed
+/// instead of removed and added again if `same_entry` matches them.
+///
+/// The returned [`RenamedReferences`] have to be applied with
+/// [`RenamedReferences::rewrite_depends`] to the mutations of resources referring to the
+/// renamed entries.
+pub fn generate_add_update_remove_by_key_with_renames<'c, 't, 'r, Resource>(
+    current: &'c [Resource],
+    target: impl IntoIterator<Item = impl Into<Cow<'t, Resource>>>,
+    same_entry: impl Fn(&Resource, &Resource) -> bool,
+) -> (
+    RenamedReferences,
+    impl Iterator<Item = ResourceMutation<'r>> + 'r,
+)
+where
+    'c: 'r,
+    't: 'r,
+    Resource: KeyedResource + CfgResource + Creatable + Updatable<Resource> + Clone + 't,
+    <Resource as KeyedResource>::Value: 'static,
+    <Resource as KeyedResource>::Key: PartialEq,
+{
+    let pairing = UpdatePairing::match_updates_by_key(current, target).match_renames(same_entry);
+    (
+        pairing.renamed_references(),
+        pairing.generate_remove_update_add(),
+    )
+}
 pub fn generate_update_by_key<'c, 't, 'r, 'e, Target, Current>(
     current: &'c [Current],
     target: impl IntoIterator<Item = impl Into<Cow<'t, Target>>>,
@@ -900,7 +2151,7 @@ where
         .map(|e| Into::into(e))
         .collect::<Vec<_>>();
     let mut matched = Vec::with_capacity(current.len().max(target_refs.len()));
-    for c in current {
+    for c in current.iter().filter(|c| c.is_user_managed()) {
         let key = c.key_value();
         if let Some((found_idx, _)) = target_refs
             .iter()
@@ -933,16 +2184,32 @@ where
     <Target as KeyedResource>::Key: PartialEq<<Current as KeyedResource>::Key>,
     Target: Clone,
 {
+    /// Pairs entries with the same key, entries which are not
+    /// [user managed](KeyedResource::is_user_managed) are skipped.
     pub fn match_updates_by_key(
         current: &'a [Current],
         target: impl IntoIterator<Item = impl Into<Cow<'b, Target>>>,
     ) -> Self {
-        let mut orphans = Vec::with_capacity(current.len());
+        Self::match_entries_by_key(current.iter().filter(|c| c.is_user_managed()), target)
+    }
+    /// Same as [`UpdatePairing::match_updates_by_key`] including the entries which are not
+    /// user managed.
+    pub fn match_updates_by_key_including_unmanaged(
+        current: &'a [Current],
+        target: impl IntoIterator<Item = impl Into<Cow<'b, Target>>>,
+    ) -> Self {
+        Self::match_entries_by_key(current.iter(), target)
+    }
+    fn match_entries_by_key(
+        current: impl Iterator<Item = &'a Current>,
+        target: impl IntoIterator<Item = impl Into<Cow<'b, Target>>>,
+    ) -> Self {
+        let mut orphans = Vec::new();
         let mut target_refs = target
             .into_iter()
             .map(|t| Into::into(t))
             .collect::<Vec<_>>();
-        let mut matched = Vec::with_capacity(current.len().max(target_refs.len()));
+        let mut matched = Vec::with_capacity(target_refs.len());
         for c in current {
             let key = c.key_value();
             if let Some((found_idx, _)) = target_refs
@@ -962,7 +2229,136 @@ where
             new_entries: target_refs.into_boxed_slice(),
         }
     }
+    /// Pairs orphaned entries with new entries `same_entry` considers the same object (e.g. by
+    /// `.id`, mac address or default name), so they are renamed by a single update instead of
+    /// being removed and added again.
+    pub fn match_renames(mut self, same_entry: impl Fn(&Current, &Target) -> bool) -> Self {
+        let mut orphans = Vec::with_capacity(self.orphaned_entries.len());
+        let mut new_entries = self.new_entries.into_vec();
+        let mut matched = self.matched_entries.into_vec();
+        for c in self.orphaned_entries {
+            if let Some(found_idx) = new_entries.iter().position(|t| same_entry(c, t)) {
+                matched.push((c, new_entries.remove(found_idx)));
+            } else {
+                orphans.push(c);
+            }
+        }
+        self.orphaned_entries = orphans.into_boxed_slice();
+        self.matched_entries = matched.into_boxed_slice();
+        self.new_entries = new_entries.into_boxed_slice();
+        self
+    }
 }
+
+impl<Resource> UpdatePairing<'_, '_, Resource, Resource>
+where
+    Resource: RosResource + CfgResource + Clone,
+{
+    /// Collects the references renamed by the matched updates, see
+    /// [`UpdatePairing::match_renames`].
+    pub fn renamed_references(&self) -> RenamedReferences {
+        let mut renamed = RenamedReferences::default();
+        for (current, target) in &self.matched_entries {
+            target.generate_derived_updates(*current, &mut renamed);
+        }
+        renamed
+    }
+}
+
+/// References renamed by updates, collected from [`DeserializeRosResource::generate_derived_updates`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RenamedReferences(Vec<RenamedReference>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenamedReference {
+    pub ref_type: ReferenceType,
+    pub old_value: Box<[u8]>,
+    pub new_value: Box<[u8]>,
+}
+
+impl FieldUpdateHandler for RenamedReferences {
+    fn update_reference<V: RosValue + 'static>(
+        &mut self,
+        ref_type: ReferenceType,
+        old_value: &V,
+        new_value: &V,
+    ) -> bool {
+        self.0.push(RenamedReference {
+            ref_type,
+            old_value: Box::from(old_value.encode_ros().as_ref()),
+            new_value: Box::from(new_value.encode_ros().as_ref()),
+        });
+        true
+    }
+}
+
+impl RenamedReferences {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn append(&mut self, other: RenamedReferences) {
+        self.0.extend(other.0);
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &RenamedReference> {
+        self.0.iter()
+    }
+    /// Rewrites mutations still depending on an old name to the new one, together with the
+    /// fields referring to it. Other fields holding the old name, e.g. a `comment`, are kept.
+    ///
+    /// Returns `true` if the mutation was modified.
+    pub fn rewrite_depends(&self, mutation: &mut ResourceMutation) -> bool {
+        let reference_fields = model::reference_fields(mutation.resource);
+        let mut modified = false;
+        for RenamedReference {
+            ref_type,
+            old_value,
+            new_value,
+        } in &self.0
+        {
+            let mut depends_on_old = false;
+            for (dep_type, dep_value) in mutation.depends.iter_mut() {
+                if dep_type == ref_type && dep_value.as_ref() == old_value.as_ref() {
+                    *dep_value = Cow::Owned(new_value.to_vec());
+                    depends_on_old = true;
+                }
+            }
+            if depends_on_old {
+                for field in mutation.fields.iter_mut() {
+                    if !reference_fields.contains(&(field.key, *ref_type)) {
+                        continue;
+                    }
+                    if let Some(value) = rename_list_entry(&field.value, old_value, new_value) {
+                        field.value = Cow::Owned(value);
+                    }
+                }
+                modified = true;
+            }
+        }
+        modified
+    }
+}
+
+/// Replaces the entries of a comma separated (and maybe negated) value equal to `old_value`
+fn rename_list_entry(value: &[u8], old_value: &[u8], new_value: &[u8]) -> Option<Vec<u8>> {
+    let mut renamed = false;
+    let entries = value
+        .split(|b| *b == b',')
+        .map(|entry| {
+            let (negation, name) = match entry.strip_prefix(b"!") {
+                Some(name) => (&b"!"[..], name),
+                None => (&b""[..], entry),
+            };
+            if name == old_value {
+                renamed = true;
+                [negation, new_value].concat()
+            } else {
+                entry.to_vec()
+            }
+        })
+        .collect::<Vec<_>>();
+    renamed.then(|| entries.join(&b','))
+}
+
 impl<'c, 't, Resource> UpdatePairing<'c, 't, Resource, Resource>
 where
     Resource: SingleResource + CfgResource + Clone,
@@ -984,6 +2380,7 @@ where
                 .changed_values(original)
                 .map(|e| e.into_owned())
                 .collect::<Box<[_]>>();
+            let unset = target.unset_fields(original).collect::<Box<[_]>>();
             let provides = target
                 .provides_reference()
                 .map(|(k, v)| (k, Cow::Owned(v.into_owned())))
@@ -996,10 +2393,668 @@ where
             ResourceMutation {
                 resource: Resource::path(),
                 operation: ResourceMutationOperation::UpdateSingle,
+                previous: previous_values(&fields, &unset, original),
                 fields,
+                unset,
+                provenance: None,
                 depends,
                 provides,
             }
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        InterfaceBridgeByName, InterfaceBridgeCfg, InterfaceBridgePortById, InterfaceBridgePortCfg,
+        InterfaceListMemberById, InterfaceListMemberCfg, InterfaceListMemberState,
+        InterfaceVlanByName, InterfaceVlanCfg, IpAddressById, IpAddressCfg, IpRouteCfg, IpVrfCfg,
+        RoutingTableCfg, SystemIdentityCfg,
+    };
+    use crate::value::{HasDisabled, HasNone, IpOrInterface};
+    use crate::{MikrotikDevice, ascii, mikrotik_model};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_parse_object_id() {
+        let parse = |sentence: &[(&[u8], Option<&[u8]>)], context| {
+            <SentenceResult<Resource> as ParsedMessage>::parse_message(sentence, &context)
+        };
+        assert!(matches!(
+            parse(&[(b"ret", Some(b"*1A"))], ReplyContext::Add),
+            SentenceResult::ObjectId { id: Id(0x1A) }
+        ));
+        assert!(matches!(
+            parse(&[(b".id", Some(b"*3"))], ReplyContext::ObjectIds),
+            SentenceResult::ObjectId { id: Id(3) }
+        ));
+        assert!(matches!(
+            parse(&[(b".id", Some(b"3"))], ReplyContext::ObjectIds),
+            SentenceResult::Error { .. }
+        ));
+        assert!(matches!(
+            parse(&[(b"message", Some(b"done"))], ReplyContext::Add),
+            SentenceResult::Error { .. }
+        ));
+        // a sentence with just an `.id` is no entry of the requested type
+        let SentenceResult::Error { errors, .. } = parse(
+            &[(b".id", Some(b"*3"))],
+            ReplyContext::Resource(ResourceType::SystemIdentityCfg),
+        ) else {
+            panic!("parsed as object id or entry");
+        };
+        assert!(matches!(
+            errors.first(),
+            Some(ResourceAccessError::UndefinedFieldError {
+                field_name: b"name"
+            })
+        ));
+    }
+
+    #[test]
+    fn test_inverse_single_update() {
+        let current = SystemIdentityCfg {
+            name: b"old-name".into(),
+        };
+        let target = SystemIdentityCfg {
+            name: b"new-name".into(),
+        };
+        let mutation = target.calculate_update(&current);
+        let inverse = mutation.inverse(&current).expect("inverse missing");
+        assert_eq!(inverse.operation, ResourceMutationOperation::UpdateSingle);
+        assert_eq!(
+            inverse.fields.as_ref(),
+            &[KeyValuePair {
+                key: b"name",
+                value: Cow::Borrowed(b"old-name"),
+            }]
+        );
+        assert_eq!(mutation.inverse_by_key::<InterfaceVlanByName>(&[]), None);
+    }
+
+    #[test]
+    fn test_revert_script() {
+        let current = SystemIdentityCfg {
+            name: b"old-name".into(),
+        };
+        let target = SystemIdentityCfg {
+            name: b"new-name".into(),
+        };
+        let mutation = target.calculate_update(&current);
+        let inverse = mutation.inverse(&current).expect("inverse missing");
+        assert_eq!(
+            revert_script([&inverse].into_iter()).to_string(),
+            "/system/scheduler\nremove [find name=mikrotik-model-revert]\n/system/identity\nset name=old-name \n"
+        );
+    }
+
+    #[test]
+    fn test_unset_optional_field() {
+        let current = [InterfaceVlanByName(InterfaceVlanCfg {
+            name: b"vlan10".into(),
+            comment: Some(b"uplink".into()),
+            vlan_id: 10,
+            ..InterfaceVlanCfg::default()
+        })];
+        let mut target = current[0].clone();
+        target.0.comment = None;
+
+        let update = target.calculate_update(&current[0]);
+        assert!(update.fields.is_empty());
+        assert_eq!(update.unset.as_ref(), &[&b"comment"[..]]);
+        let mut script = String::new();
+        Generator::new(&mut script)
+            .append_mutation(&update)
+            .unwrap();
+        assert_eq!(
+            script,
+            "/interface/vlan\nunset [find name=vlan10] value-name=comment\n"
+        );
+        let inverse = update.inverse_by_key(&current).expect("inverse missing");
+        assert_eq!(
+            inverse.fields.as_ref(),
+            &[KeyValuePair {
+                key: b"comment",
+                value: Cow::Borrowed(b"uplink"),
+            }]
+        );
+        let mut added_comment = current[0].clone();
+        added_comment.0.comment = Some(b"new".into());
+        let without_comment = [target];
+        let update = added_comment.calculate_update(&without_comment[0]);
+        let inverse = update
+            .inverse_by_key(&without_comment)
+            .expect("inverse missing");
+        assert!(inverse.fields.is_empty());
+        assert_eq!(inverse.unset.as_ref(), &[&b"comment"[..]]);
+    }
+
+    #[test]
+    fn test_dynamic_entry_not_user_managed() {
+        let member = |dynamic| {
+            (
+                InterfaceListMemberById {
+                    id: b"*1".into(),
+                    data: InterfaceListMemberCfg {
+                        comment: None,
+                        disabled: false,
+                        interface: b"ether1".into(),
+                        list: HasNone::Value(b"LAN".into()),
+                    },
+                },
+                InterfaceListMemberState {
+                    about: Default::default(),
+                    dynamic,
+                    id: b"*1".into(),
+                },
+            )
+        };
+        assert!(member(false).is_user_managed());
+        assert!(!member(true).is_user_managed());
+
+        let no_target = Vec::<Cow<InterfaceListMemberCfg>>::new();
+        let current = [member(true)];
+        assert_eq!(
+            generate_add_update_remove_by_id(&current, no_target.clone()).count(),
+            0
+        );
+        let removed =
+            generate_add_update_remove_by_id_including_unmanaged(&current, no_target.clone())
+                .collect::<Vec<_>>();
+        assert_eq!(removed.len(), 1);
+        assert!(matches!(
+            removed[0].operation,
+            ResourceMutationOperation::RemoveByKey(_)
+        ));
+        let current = [member(false)];
+        assert_eq!(
+            generate_add_update_remove_by_id(&current, no_target).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_inverse_by_key() {
+        let vlan = InterfaceVlanCfg {
+            name: b"vlan10".into(),
+            interface: b"bridge".into(),
+            vlan_id: 10,
+            ..InterfaceVlanCfg::default()
+        };
+        let current = [InterfaceVlanByName(vlan)];
+        let mut target = current[0].clone();
+        target.0.vlan_id = 20;
+
+        let update = target.calculate_update(&current[0]);
+        let inverse = update.inverse_by_key(&current).expect("inverse missing");
+        assert_eq!(
+            inverse.operation,
+            ResourceMutationOperation::UpdateByKey(KeyValuePair {
+                key: b"name",
+                value: Cow::Borrowed(b"vlan10"),
+            })
+        );
+        assert_eq!(
+            inverse.fields.as_ref(),
+            &[KeyValuePair {
+                key: b"vlan-id",
+                value: Cow::Borrowed(b"10"),
+            }]
+        );
+
+        let remove =
+            generate_add_update_remove_by_key(&current, Vec::<Cow<InterfaceVlanByName>>::new())
+                .next()
+                .expect("remove missing");
+        let inverse = remove.inverse_by_key(&current).expect("inverse missing");
+        assert_eq!(inverse.operation, ResourceMutationOperation::Add);
+        assert_eq!(inverse.fields, current[0].0.calculate_create().fields);
+    }
+
+    #[test]
+    fn test_resolve_pending_id() {
+        let vlan = InterfaceVlanCfg {
+            name: b"vlan10".into(),
+            vlan_id: 10,
+            ..InterfaceVlanCfg::default()
+        };
+        let pending = PendingId {
+            resource: InterfaceVlanCfg::path(),
+            index: 1,
+        };
+        let add = vlan.calculate_create().provides_pending(pending);
+        let update = ResourceMutation {
+            resource: InterfaceVlanCfg::path(),
+            operation: ResourceMutationOperation::UpdateByKey(pending.key()),
+            fields: Box::new([KeyValuePair {
+                key: b"vlan-id",
+                value: Cow::Borrowed(b"20"),
+            }]),
+            previous: Box::new([]),
+            unset: Box::new([]),
+            provenance: None,
+            depends: Box::new([]),
+            provides: Box::new([]),
+        }
+        .depends_on_pending(pending);
+        let mutations = [update, add];
+        let sorted = ResourceMutation::sort_mutations(&mutations).expect("unresolved dependencies");
+        assert_eq!(sorted[0].operation, ResourceMutationOperation::Add);
+
+        let mut assigned_ids = AssignedIds::default();
+        assert!(matches!(
+            assigned_ids.resolve(sorted[1]),
+            Err(Error::UnresolvedPendingId(_))
+        ));
+        assigned_ids.record(sorted[0], &MutationOutcome::Applied { id: Some(Id(0x1a)) });
+        let resolved = assigned_ids
+            .resolve(sorted[1])
+            .expect("pending id not resolved");
+        assert_eq!(
+            resolved.operation,
+            ResourceMutationOperation::UpdateByKey(KeyValuePair {
+                key: b".id",
+                value: Cow::Borrowed(b"*1A"),
+            })
+        );
+    }
+
+    #[test]
+    fn test_pending_id_by_id() {
+        let port = |interface: &[u8]| InterfaceBridgePortCfg {
+            bridge: b"br0".into(),
+            interface: interface.into(),
+            ..InterfaceBridgePortCfg::default()
+        };
+        let current = [InterfaceBridgePortById {
+            id: Id(1),
+            data: port(b"ether1"),
+        }];
+        let target = [port(b"ether1"), port(b"ether2")];
+        let mutations =
+            generate_add_update_remove_by_id(&current, target.iter().map(Cow::Borrowed))
+                .collect::<Vec<_>>();
+        assert_eq!(mutations.len(), 2);
+        let add = &mutations[1];
+        assert_eq!(add.operation, ResourceMutationOperation::Add);
+        let pending = PendingId {
+            resource: InterfaceBridgePortCfg::path(),
+            index: 1,
+        };
+        assert_eq!(add.provides.as_ref(), &[pending.reference()]);
+
+        // a following step of the plan sets the added port by its pending id
+        let update = ResourceMutation {
+            resource: InterfaceBridgePortCfg::path(),
+            operation: ResourceMutationOperation::UpdateByKey(pending.key()),
+            fields: Box::new([KeyValuePair {
+                key: b"pvid",
+                value: Cow::Borrowed(b"10"),
+            }]),
+            previous: Box::new([]),
+            unset: Box::new([]),
+            provenance: None,
+            depends: Box::new([]),
+            provides: Box::new([]),
+        }
+        .depends_on_pending(pending);
+        let mut assigned_ids = AssignedIds::default();
+        assigned_ids.record(add, &MutationOutcome::Applied { id: Some(Id(0x2b)) });
+        let resolved = assigned_ids
+            .resolve(&update)
+            .expect("pending id not resolved");
+        assert_eq!(
+            resolved.operation,
+            ResourceMutationOperation::UpdateByKey(KeyValuePair {
+                key: b".id",
+                value: Cow::Borrowed(b"*2B"),
+            })
+        );
+    }
+
+    #[test]
+    fn test_route_after_routing_table() {
+        let route = IpRouteCfg {
+            id: b"".into(),
+            check_gateway: None,
+            comment: None,
+            dst_address: "10.0.0.0/8".parse().unwrap(),
+            gateway: IpOrInterface::Ip("192.168.1.1".parse().unwrap()),
+            local_address: None,
+            routing_table: Some(b"vpn".into()),
+            scope: None,
+            suppress_hw_offload: None,
+            target_scope: None,
+            vrf_interface: None,
+        };
+        let table = RoutingTableCfg {
+            comment: None,
+            disabled: None,
+            fib: Some(true),
+            name: b"vpn".into(),
+        };
+        let mutations = [route.calculate_create(), table.calculate_create()];
+        let sorted = ResourceMutation::sort_mutations(&mutations).expect("unresolved dependencies");
+        assert_eq!(sorted[0].resource, RoutingTableCfg::path());
+        assert_eq!(sorted[1].resource, IpRouteCfg::path());
+
+        // a vrf provides the routing table of the same name
+        let vrf = IpVrfCfg {
+            comment: None,
+            disabled: None,
+            interfaces: Default::default(),
+            name: b"vpn".into(),
+        };
+        let mutations = [route.calculate_create(), vrf.calculate_create()];
+        let sorted = ResourceMutation::sort_mutations(&mutations).expect("unresolved dependencies");
+        assert_eq!(sorted[0].resource, IpVrfCfg::path());
+        assert_eq!(sorted[1].resource, IpRouteCfg::path());
+    }
+
+    #[test]
+    fn test_generate_ordered_by_id() {
+        let entry = |comment: &[u8]| IpAddressCfg {
+            comment: Some(comment.into()),
+            ..IpAddressCfg::default()
+        };
+        let current = [
+            IpAddressById {
+                id: b"*1".into(),
+                data: entry(b"a"),
+            },
+            IpAddressById {
+                id: b"*2".into(),
+                data: entry(b"b"),
+            },
+            IpAddressById {
+                id: b"*3".into(),
+                data: entry(b"c"),
+            },
+        ];
+        let target = [entry(b"c"), entry(b"a"), entry(b"d"), entry(b"e")];
+        let mutations =
+            generate_ordered_by_id(&current, target.iter().map(Cow::Borrowed)).collect::<Vec<_>>();
+        let id_key = |id: u32| KeyValuePair {
+            key: b".id",
+            value: Cow::Owned(Id(id).encode_ros().into_owned()),
+        };
+        let operations = mutations
+            .iter()
+            .map(|mutation| mutation.operation.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            operations,
+            vec![
+                ResourceMutationOperation::UpdateByKey(id_key(2)),
+                ResourceMutationOperation::Move {
+                    key: id_key(3),
+                    destination: Some(id_key(1)),
+                },
+                ResourceMutationOperation::Add,
+            ]
+        );
+        // appended entries need no place-before and no move
+        assert!(
+            !mutations[2]
+                .fields
+                .iter()
+                .any(|kv| kv.key == b"place-before")
+        );
+        let pending = PendingId {
+            resource: IpAddressCfg::path(),
+            index: 3,
+        };
+        assert_eq!(mutations[2].provides.as_ref(), &[pending.reference()]);
+
+        let mut script = String::new();
+        Generator::new(&mut script)
+            .append_mutation(&mutations[1])
+            .expect("Cannot write to string");
+        assert_eq!(
+            script,
+            "/ip/address\nmove [find .id=*3] destination=[find .id=*1]\n"
+        );
+
+        let target = [
+            entry(b"a"),
+            entry(b"x"),
+            entry(b"y"),
+            entry(b"b"),
+            entry(b"c"),
+        ];
+        let current = [
+            IpAddressById {
+                id: b"*1".into(),
+                data: entry(b"a"),
+            },
+            IpAddressById {
+                id: b"*2".into(),
+                data: entry(b"b"),
+            },
+            IpAddressById {
+                id: b"*3".into(),
+                data: entry(b"c"),
+            },
+        ];
+        let mutations =
+            generate_ordered_by_id(&current, target.iter().map(Cow::Borrowed)).collect::<Vec<_>>();
+        assert_eq!(mutations.len(), 2);
+        for mutation in &mutations {
+            assert_eq!(mutation.operation, ResourceMutationOperation::Add);
+            assert!(mutation.fields.contains(&KeyValuePair {
+                key: b"place-before",
+                value: Cow::Borrowed(b"*2"),
+            }));
+        }
+        let first_added = PendingId {
+            resource: IpAddressCfg::path(),
+            index: 1,
+        };
+        assert_eq!(mutations[1].depends.as_ref(), &[first_added.reference()]);
+    }
+
+    mikrotik_model!(
+        name = RenameData,
+        fields(
+            bridge(by_key(
+                path = "interface/bridge",
+                key = name,
+                rename = same_bridge
+            )),
+            bridge_port(by_id(
+                path = "interface/bridge/port",
+                keys(bridge, interface)
+            )),
+        ),
+    );
+    fn same_bridge(current: &InterfaceBridgeByName, target: &InterfaceBridgeByName) -> bool {
+        current.0.comment == target.0.comment
+    }
+
+    #[test]
+    fn test_rename_in_model() {
+        let bridge = |name: &[u8]| {
+            InterfaceBridgeByName(InterfaceBridgeCfg {
+                name: name.into(),
+                comment: Some(b"lan".into()),
+                ..InterfaceBridgeCfg::default()
+            })
+        };
+        let current = RenameDataCurrent {
+            bridge: Box::new([bridge(b"br0")]),
+            bridge_port: Box::new([]),
+        };
+        let port = InterfaceBridgePortCfg {
+            bridge: b"br0".into(),
+            interface: b"ether2".into(),
+            ..InterfaceBridgePortCfg::default()
+        };
+        let target = RenameDataTarget {
+            bridge: BTreeMap::from([(b"lan".into(), bridge(b"lan"))]),
+            bridge_port: BTreeMap::from([((b"br0".into(), b"ether2".into()), port)]),
+        };
+        let mutations = target.generate_mutations(&current).unwrap();
+        assert_eq!(mutations.len(), 2);
+        let rename = mutations
+            .iter()
+            .find(|m| m.resource == InterfaceBridgeCfg::path())
+            .unwrap();
+        assert_eq!(
+            rename.operation,
+            ResourceMutationOperation::UpdateByKey(KeyValuePair {
+                key: b"name",
+                value: Cow::Borrowed(b"br0"),
+            })
+        );
+        let add_port = mutations
+            .iter()
+            .find(|m| m.resource == InterfaceBridgePortCfg::path())
+            .unwrap();
+        assert_eq!(add_port.operation, ResourceMutationOperation::Add);
+        assert!(add_port.fields.contains(&KeyValuePair {
+            key: b"bridge",
+            value: Cow::Borrowed(b"lan"),
+        }));
+        assert!(
+            add_port
+                .depends
+                .contains(&(ReferenceType::Interface, Cow::Borrowed(&b"lan"[..])))
+        );
+    }
+
+    #[test]
+    fn test_rename_by_key() {
+        let vlan = |name: &[u8]| {
+            InterfaceVlanByName(InterfaceVlanCfg {
+                name: name.into(),
+                interface: b"ether1".into(),
+                vlan_id: 10,
+                ..InterfaceVlanCfg::default()
+            })
+        };
+        let current = [vlan(b"e01")];
+        let target = [vlan(b"uplink")];
+        let same_entry = |c: &InterfaceVlanByName, t: &InterfaceVlanByName| {
+            c.0.interface == t.0.interface && c.0.vlan_id == t.0.vlan_id
+        };
+        let pairing =
+            UpdatePairing::match_updates_by_key(&current, target.iter().map(Cow::Borrowed))
+                .match_renames(same_entry);
+        let renamed = pairing.renamed_references();
+        assert_eq!(
+            renamed.iter().collect::<Vec<_>>(),
+            vec![&RenamedReference {
+                ref_type: ReferenceType::Interface,
+                old_value: Box::from(&b"e01"[..]),
+                new_value: Box::from(&b"uplink"[..]),
+            }]
+        );
+        let mutations = pairing.generate_remove_update_add().collect::<Vec<_>>();
+        assert_eq!(mutations.len(), 1);
+        assert_eq!(
+            mutations[0].operation,
+            ResourceMutationOperation::UpdateByKey(KeyValuePair {
+                key: b"name",
+                value: Cow::Borrowed(b"e01"),
+            })
+        );
+        assert_eq!(
+            mutations[0].fields.as_ref(),
+            &[KeyValuePair {
+                key: b"name",
+                value: Cow::Borrowed(b"uplink"),
+            }]
+        );
+
+        let mut dependent = ResourceMutation {
+            resource: IpAddressCfg::path(),
+            operation: ResourceMutationOperation::Add,
+            fields: Box::new([
+                KeyValuePair {
+                    key: b"interface",
+                    value: Cow::Borrowed(b"e01"),
+                },
+                KeyValuePair {
+                    key: b"comment",
+                    value: Cow::Borrowed(b"e01"),
+                },
+            ]),
+            previous: Box::new([]),
+            unset: Box::new([]),
+            provenance: None,
+            depends: Box::new([(ReferenceType::Interface, Cow::Borrowed(b"e01"))]),
+            provides: Box::new([]),
+        };
+        assert!(renamed.rewrite_depends(&mut dependent));
+        assert_eq!(dependent.fields[0].value.as_ref(), b"uplink");
+        assert_eq!(dependent.fields[1].value.as_ref(), b"e01");
+        assert_eq!(
+            dependent.depends.as_ref(),
+            &[(ReferenceType::Interface, Cow::Borrowed(&b"uplink"[..]))]
+        );
+    }
+
+    #[test]
+    fn test_serde_ros_names() {
+        let vlan = InterfaceVlanByName(InterfaceVlanCfg {
+            name: b"vlan-10".into(),
+            vlan_id: 10,
+            interface: b"ether1".into(),
+            ..InterfaceVlanCfg::default()
+        });
+        let yaml = serde_yaml::to_string(&vlan).unwrap();
+        assert!(yaml.contains("\nloop-protect-disable-time: 5m\n"));
+        assert!(yaml.contains("\nl2mtu: '1556'\n"));
+        assert!(!yaml.contains("comment"));
+        assert_eq!(
+            serde_yaml::from_str::<InterfaceVlanByName>(&yaml).unwrap(),
+            vlan
+        );
+        let edited =
+            serde_yaml::from_str::<InterfaceVlanCfg>(&yaml.replace("vlan-id: '10'", "vlan-id: 20"))
+                .unwrap();
+        assert_eq!(edited.vlan_id, 20);
+        assert!(serde_yaml::from_str::<InterfaceVlanCfg>("vlan-id: 10\n").is_err());
+        let error = serde_yaml::from_str::<InterfaceVlanCfg>(&format!("{yaml}unknown: x\n"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("unknown field `unknown`, expected one of"));
+        assert!(error.contains("`vlan-id`"));
+    }
+
+    #[test]
+    fn test_serde_duration() {
+        let mut vlan = InterfaceVlanCfg {
+            name: b"vlan-10".into(),
+            ..InterfaceVlanCfg::default()
+        };
+        vlan.loop_protect_disable_time = Duration::from_secs(93784);
+        let yaml = serde_yaml::to_string(&vlan).unwrap();
+        assert!(yaml.contains("\nloop-protect-disable-time: 1d2h3m4s\n"));
+        assert_eq!(
+            serde_yaml::from_str::<InterfaceVlanCfg>(&yaml).unwrap(),
+            vlan
+        );
+        assert_eq!(
+            vlan.loop_protect_disable_time.encode_ros().as_ref(),
+            b"93784s"
+        );
+        assert_eq!(
+            HasDisabled::Value(Duration::from_secs(300))
+                .encode_readable()
+                .as_ref(),
+            b"5m"
+        );
+        assert_eq!(
+            Some(BTreeSet::from([
+                Duration::from_secs(60),
+                Duration::from_secs(90)
+            ]))
+            .encode_readable()
+            .as_ref(),
+            b"1m,1m30s"
+        );
+    }
+}
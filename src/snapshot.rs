@@ -0,0 +1,108 @@
+use crate::{
+    MikrotikDevice,
+    hwconfig::DeviceType,
+    model::{Data, SystemResourceState},
+    resource::{self, SingleResource},
+};
+use encoding_rs::mem::decode_latin1;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{Read, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+/// Layout version written by [`Snapshot::write_to`], increased on incompatible changes
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("Cannot read or write snapshot: {0}")]
+    Format(#[from] serde_yaml::Error),
+    #[error("Snapshot version {0} is not supported, expected {SNAPSHOT_VERSION}")]
+    UnsupportedVersion(u64),
+    #[error("Snapshot has no version")]
+    MissingVersion,
+}
+
+/// Device state stored on disk, so mutations can be calculated later without connecting to
+/// the device, e.g. with the `from_data` constructor generated by `mikrotik_model!`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// board name as reported by `/system/resource`, see [`Snapshot::device_type`]
+    pub board_name: Option<String>,
+    pub routeros_version: String,
+    /// seconds since the unix epoch
+    pub timestamp: u64,
+    pub data: Data,
+}
+
+impl Snapshot {
+    pub async fn fetch_from_device(device: &MikrotikDevice) -> Result<Self, resource::Error> {
+        let system_resource = SystemResourceState::fetch(device)
+            .await?
+            .ok_or(resource::Error::ErrorFetchingSingleItem)?;
+        let data = Data::fetch_from_device(device).await?;
+        Ok(Self {
+            version: SNAPSHOT_VERSION,
+            board_name: system_resource
+                .board_name
+                .map(|name| decode_latin1(&name).into_owned()),
+            routeros_version: decode_latin1(&system_resource.version).into_owned(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            data,
+        })
+    }
+    pub fn device_type(&self) -> Option<DeviceType> {
+        DeviceType::type_by_name(self.board_name.as_deref()?.as_bytes())
+    }
+    pub fn write_to(&self, writer: impl Write) -> Result<(), SnapshotError> {
+        Ok(serde_yaml::to_writer(writer, self)?)
+    }
+    /// Reads a snapshot, the version is checked before the content is parsed.
+    pub fn read_from(reader: impl Read) -> Result<Self, SnapshotError> {
+        let value: serde_yaml::Value = serde_yaml::from_reader(reader)?;
+        match value.get("version").map(serde_yaml::Value::as_u64) {
+            None => return Err(SnapshotError::MissingVersion),
+            Some(Some(version)) if version == u64::from(SNAPSHOT_VERSION) => {}
+            Some(version) => return Err(SnapshotError::UnsupportedVersion(version.unwrap_or(0))),
+        }
+        Ok(serde_yaml::from_value(value)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let device_type = DeviceType::Crs32624g2splus;
+        let mut data = device_type.generate_empty_data();
+        data.system_identity_cfg.name = b"switch".into();
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            board_name: Some(device_type.device_type_name().to_string()),
+            routeros_version: "7.16.2 (stable)".to_string(),
+            timestamp: 1_700_000_000,
+            data,
+        };
+        let mut written = Vec::new();
+        snapshot.write_to(&mut written).unwrap();
+        let read = Snapshot::read_from(written.as_slice()).unwrap();
+        assert_eq!(read, snapshot);
+        assert_eq!(read.device_type(), Some(device_type));
+
+        let newer = String::from_utf8(written)
+            .unwrap()
+            .replacen("version: 1\n", "version: 2\n", 1);
+        assert!(matches!(
+            Snapshot::read_from(newer.as_bytes()),
+            Err(SnapshotError::UnsupportedVersion(2))
+        ));
+    }
+}
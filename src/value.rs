@@ -76,6 +76,11 @@ impl<V: Copy> Copy for ParseRosValueResult<V> {}
 pub trait RosValue: Sized + Debug + 'static {
     fn parse_ros(value: &[u8]) -> ParseRosValueResult<Self>;
     fn encode_ros(&self) -> Cow<'_, [u8]>;
+    /// Textual value meant to be read by humans, e.g. `5m` instead of `300s`. It has to be
+    /// accepted by [`RosValue::parse_ros`] as well.
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        self.encode_ros()
+    }
 }
 
 impl RosValue for Box<[u8]> {
@@ -224,6 +229,27 @@ impl RosValue for Duration {
     fn encode_ros(&self) -> Cow<'_, [u8]> {
         Cow::Owned(Vec::from(format!("{}s", self.as_secs()).as_bytes()))
     }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        let mut remaining = self.as_secs();
+        if remaining == 0 {
+            return b"0s".into();
+        }
+        let mut encoded = String::new();
+        for (unit, seconds) in [
+            ('w', 7 * 24 * 3600),
+            ('d', 24 * 3600),
+            ('h', 3600),
+            ('m', 60),
+            ('s', 1),
+        ] {
+            if remaining >= seconds {
+                encoded.push_str(&format!("{}{unit}", remaining / seconds));
+                remaining %= seconds;
+            }
+        }
+        encoded.into_bytes().into()
+    }
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub struct ClockFrequency(pub u32);
@@ -244,6 +270,31 @@ impl RosValue for ClockFrequency {
     }
 }
 
+/// Autonomous system number, accepted in asplain (`65536`) and asdot (`1.0`) notation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Ord, PartialOrd, Default)]
+pub struct AsNumber(pub u32);
+impl RosValue for AsNumber {
+    fn parse_ros(value: &[u8]) -> ParseRosValueResult<Self> {
+        let value = String::from_utf8_lossy(value);
+        let parsed = match value.split_once('.') {
+            None => value.parse::<u32>().ok(),
+            Some((high, low)) => high
+                .parse::<u16>()
+                .ok()
+                .zip(low.parse::<u16>().ok())
+                .map(|(high, low)| (u32::from(high) << 16) | u32::from(low)),
+        };
+        match parsed {
+            Some(v) => ParseRosValueResult::Value(AsNumber(v)),
+            None => ParseRosValueResult::Invalid,
+        }
+    }
+
+    fn encode_ros(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Vec::from(self.0.to_string().as_bytes()))
+    }
+}
+
 impl RosValue for MacAddress {
     fn parse_ros(value: &[u8]) -> ParseRosValueResult<Self> {
         match MacAddress::from_str(String::from_utf8_lossy(value).as_ref()) {
@@ -275,6 +326,13 @@ impl<V: RosValue> RosValue for Option<V> {
             Some(v) => v.encode_ros(),
         }
     }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        match self {
+            None => b"".into(),
+            Some(v) => v.encode_readable(),
+        }
+    }
 }
 impl<V: RosValue + Hash + Eq> RosValue for HashSet<V> {
     fn parse_ros(value: &[u8]) -> ParseRosValueResult<Self> {
@@ -288,7 +346,30 @@ impl<V: RosValue + Hash + Eq> RosValue for HashSet<V> {
     }
 
     fn encode_ros(&self) -> Cow<'_, [u8]> {
-        encode_ros_multiple(self.iter())
+        encode_ros_multiple(self.iter(), RosValue::encode_ros)
+    }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        encode_ros_multiple(self.iter(), RosValue::encode_readable)
+    }
+}
+impl<V: RosValue> RosValue for Vec<V> {
+    fn parse_ros(value: &[u8]) -> ParseRosValueResult<Self> {
+        let mut result = Vec::new();
+        match parse_ros_multiple(value, |v| {
+            result.push(v);
+        }) {
+            Ok(_) => ParseRosValueResult::Value(result),
+            Err(_) => ParseRosValueResult::Invalid,
+        }
+    }
+
+    fn encode_ros(&self) -> Cow<'_, [u8]> {
+        encode_ros_multiple(self.iter(), RosValue::encode_ros)
+    }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        encode_ros_multiple(self.iter(), RosValue::encode_readable)
     }
 }
 impl<V: RosValue + Ord + Eq> RosValue for BTreeSet<V> {
@@ -303,7 +384,11 @@ impl<V: RosValue + Ord + Eq> RosValue for BTreeSet<V> {
     }
 
     fn encode_ros(&self) -> Cow<'_, [u8]> {
-        encode_ros_multiple(self.iter())
+        encode_ros_multiple(self.iter(), RosValue::encode_ros)
+    }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        encode_ros_multiple(self.iter(), RosValue::encode_readable)
     }
 }
 
@@ -322,15 +407,16 @@ fn parse_ros_multiple<V: RosValue>(value: &[u8], mut appender: impl FnMut(V)) ->
     Ok(())
 }
 
-fn encode_ros_multiple<'a>(
-    values: impl Iterator<Item = &'a (impl RosValue + 'a)>,
+fn encode_ros_multiple<'a, V: RosValue + 'a>(
+    values: impl Iterator<Item = &'a V>,
+    encode: impl Fn(&'a V) -> Cow<'a, [u8]>,
 ) -> Cow<'static, [u8]> {
     let mut ret = Vec::new();
     for value in values {
         if !ret.is_empty() {
             ret.push(b',');
         }
-        ret.extend_from_slice(value.encode_ros().as_ref());
+        ret.extend_from_slice(encode(value).as_ref());
     }
     ret.into()
 }
@@ -358,6 +444,42 @@ impl<V: RosValue> RosValue for Auto<V> {
             Auto::Value(v) => v.encode_ros(),
         }
     }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        match self {
+            Auto::Value(v) => v.encode_readable(),
+            other => other.encode_ros(),
+        }
+    }
+}
+/// Value given by name or by number, like `protocol=gre` or `protocol=47`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NameOrNumber<V: RosValue> {
+    Name(V),
+    Number(u8),
+}
+impl<V: RosValue> RosValue for NameOrNumber<V> {
+    fn parse_ros(value: &[u8]) -> ParseRosValueResult<Self> {
+        match RosValue::parse_ros(value) {
+            ParseRosValueResult::Value(v) => ParseRosValueResult::Value(NameOrNumber::Name(v)),
+            ParseRosValueResult::None => ParseRosValueResult::None,
+            ParseRosValueResult::Invalid => u8::parse_ros(value).map(NameOrNumber::Number),
+        }
+    }
+
+    fn encode_ros(&self) -> Cow<'_, [u8]> {
+        match self {
+            NameOrNumber::Name(v) => v.encode_ros(),
+            NameOrNumber::Number(n) => n.encode_ros(),
+        }
+    }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        match self {
+            NameOrNumber::Name(v) => v.encode_readable(),
+            NameOrNumber::Number(n) => n.encode_ros(),
+        }
+    }
 }
 #[derive(Debug, Clone, PartialEq, PartialOrd, Hash, Eq, Ord)]
 pub enum PossibleRangeDash<V: RosValue> {
@@ -418,6 +540,19 @@ impl<V: RosValue + Clone + PartialEq> RosValue for PossibleRangeDash<V> {
             PossibleRangeDash::Single(value) => value.encode_ros(),
         }
     }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        match self {
+            PossibleRangeDash::Range { start, end } => [
+                start.encode_readable().as_ref(),
+                b"-",
+                end.encode_readable().as_ref(),
+            ]
+            .concat()
+            .into(),
+            PossibleRangeDash::Single(value) => value.encode_readable(),
+        }
+    }
 }
 impl<V: RosValue + Clone + PartialEq> RosValue for PossibleRangeDot<V> {
     fn parse_ros(value: &[u8]) -> ParseRosValueResult<Self> {
@@ -454,6 +589,19 @@ impl<V: RosValue + Clone + PartialEq> RosValue for PossibleRangeDot<V> {
             PossibleRangeDot::Single(value) => value.encode_ros(),
         }
     }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        match self {
+            PossibleRangeDot::Range { start, end } => [
+                start.encode_readable().as_ref(),
+                b"..",
+                end.encode_readable().as_ref(),
+            ]
+            .concat()
+            .into(),
+            PossibleRangeDot::Single(value) => value.encode_readable(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -491,6 +639,16 @@ impl<V: RosValue> RosValue for RxTxPair<V> {
         .concat()
         .into()
     }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        [
+            self.rx.encode_readable().as_ref(),
+            b"/",
+            self.tx.encode_readable().as_ref(),
+        ]
+        .concat()
+        .into()
+    }
 }
 impl<V: Default + RosValue> Default for RxTxPair<V> {
     fn default() -> Self {
@@ -536,6 +694,16 @@ impl<V: RosValue> RosValue for StatsPair<V> {
         .concat()
         .into()
     }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        [
+            self.rx.encode_readable().as_ref(),
+            b",",
+            self.tx.encode_readable().as_ref(),
+        ]
+        .concat()
+        .into()
+    }
 }
 impl<V: Default + RosValue> Default for StatsPair<V> {
     fn default() -> Self {
@@ -580,7 +748,79 @@ impl<V: RosValue> RosValue for HasNone<V> {
             HasNone::Value(v) => v.encode_ros(),
         }
     }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        match self {
+            HasNone::Value(v) => v.encode_readable(),
+            other => other.encode_ros(),
+        }
+    }
+}
+/// Matcher value that may be negated with a leading `!`, e.g. `src-address=!10.0.0.0/8`
+#[derive(Debug, Clone, PartialEq, Ord, Eq, PartialOrd, Hash, Default)]
+pub struct Negatable<V: RosValue> {
+    pub negated: bool,
+    pub value: V,
+}
+
+impl<V: RosValue> Negatable<V> {
+    pub fn is(value: V) -> Self {
+        Self {
+            negated: false,
+            value,
+        }
+    }
+    pub fn not(value: V) -> Self {
+        Self {
+            negated: true,
+            value,
+        }
+    }
+}
+
+impl<V: RosValue> From<V> for Negatable<V> {
+    fn from(value: V) -> Self {
+        Self::is(value)
+    }
+}
+
+impl<V: RosValue> RosValue for Negatable<V> {
+    fn parse_ros(value: &[u8]) -> ParseRosValueResult<Self> {
+        let (negated, value) = match value.strip_prefix(b"!") {
+            Some(b"") => return ParseRosValueResult::Invalid,
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+        match V::parse_ros(value) {
+            ParseRosValueResult::Value(value) => {
+                ParseRosValueResult::Value(Self { negated, value })
+            }
+            ParseRosValueResult::None => ParseRosValueResult::None,
+            ParseRosValueResult::Invalid => ParseRosValueResult::Invalid,
+        }
+    }
+
+    fn encode_ros(&self) -> Cow<'_, [u8]> {
+        if self.negated {
+            let mut encoded = b"!".to_vec();
+            encoded.extend_from_slice(&self.value.encode_ros());
+            encoded.into()
+        } else {
+            self.value.encode_ros()
+        }
+    }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        if self.negated {
+            let mut encoded = b"!".to_vec();
+            encoded.extend_from_slice(&self.value.encode_readable());
+            encoded.into()
+        } else {
+            self.value.encode_readable()
+        }
+    }
 }
+
 #[derive(Debug, Clone, PartialEq, Default)]
 pub enum HasUnlimited<V: RosValue> {
     #[default]
@@ -606,6 +846,13 @@ impl<V: RosValue> RosValue for HasUnlimited<V> {
             HasUnlimited::Value(v) => v.encode_ros(),
         }
     }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        match self {
+            HasUnlimited::Value(v) => v.encode_readable(),
+            other => other.encode_ros(),
+        }
+    }
 }
 #[derive(Debug, Clone, PartialEq, Ord, Eq, Hash, PartialOrd)]
 pub enum HasDisabled<V: RosValue> {
@@ -631,6 +878,13 @@ impl<V: RosValue> RosValue for HasDisabled<V> {
             HasDisabled::Value(v) => v.encode_ros(),
         }
     }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        match self {
+            HasDisabled::Value(v) => v.encode_readable(),
+            other => other.encode_ros(),
+        }
+    }
 }
 #[derive(Debug, Clone, PartialEq, Ord, Eq, Hash, PartialOrd)]
 pub enum HasNever<V: RosValue> {
@@ -656,6 +910,13 @@ impl<V: RosValue> RosValue for HasNever<V> {
             HasNever::Value(v) => v.encode_ros(),
         }
     }
+
+    fn encode_readable(&self) -> Cow<'_, [u8]> {
+        match self {
+            HasNever::Value(v) => v.encode_readable(),
+            other => other.encode_ros(),
+        }
+    }
 }
 
 impl RosValue for IpAddr {
@@ -935,6 +1196,64 @@ mod tests {
         assert_eq!(encoded.as_ref(), b"0x8000");
     }
     #[test]
+    fn test_duration() {
+        let parsed: ParseRosValueResult<Duration> = RosValue::parse_ros(b"1d2h30s");
+        assert_eq!(
+            parsed,
+            ParseRosValueResult::Value(Duration::from_secs(24 * 3600 + 2 * 3600 + 30))
+        );
+        assert_eq!(Duration::from_secs(300).encode_ros().as_ref(), b"300s");
+    }
+    #[test]
+    fn test_negatable() {
+        let parsed: ParseRosValueResult<Negatable<IpNet>> = RosValue::parse_ros(b"!10.0.0.0/8");
+        let ParseRosValueResult::Value(negated) = parsed else {
+            panic!("cannot parse {parsed:?}")
+        };
+        assert_eq!(
+            negated,
+            Negatable::not(IpNet::from_str("10.0.0.0/8").unwrap())
+        );
+        assert_eq!(negated.encode_ros().as_ref(), b"!10.0.0.0/8");
+
+        let parsed: ParseRosValueResult<Negatable<BTreeSet<PossibleRangeDash<u16>>>> =
+            RosValue::parse_ros(b"!22,8000-8080");
+        let ParseRosValueResult::Value(ports) = parsed else {
+            panic!("cannot parse {parsed:?}")
+        };
+        assert!(ports.negated);
+        assert_eq!(ports.value.len(), 2);
+        assert!(ports.encode_ros().starts_with(b"!"));
+        assert_eq!(
+            RosValue::parse_ros(&ports.encode_ros()),
+            ParseRosValueResult::Value(ports.clone())
+        );
+
+        let parsed: ParseRosValueResult<Negatable<AsciiString>> = RosValue::parse_ros(b"LAN");
+        assert_eq!(
+            parsed,
+            ParseRosValueResult::Value(Negatable::is(b"LAN".into()))
+        );
+        let parsed: ParseRosValueResult<Negatable<AsciiString>> = RosValue::parse_ros(b"!");
+        assert_eq!(parsed, ParseRosValueResult::Invalid);
+    }
+    #[test]
+    fn test_as_number() {
+        assert_eq!(
+            AsNumber::parse_ros(b"65000"),
+            ParseRosValueResult::Value(AsNumber(65000))
+        );
+        assert_eq!(
+            AsNumber::parse_ros(b"1.10"),
+            ParseRosValueResult::Value(AsNumber(65546))
+        );
+        assert_eq!(AsNumber(65546).encode_ros().as_ref(), b"65546");
+        assert_eq!(
+            AsNumber::parse_ros(b"65536.1"),
+            ParseRosValueResult::Invalid
+        );
+    }
+    #[test]
     fn test_parse_id() {
         let parsed: ParseRosValueResult<Id> = RosValue::parse_ros(b"*4");
         assert_eq!(parsed, ParseRosValueResult::Value(Id(4)));